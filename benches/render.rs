@@ -0,0 +1,29 @@
+use std::time::Duration;
+use criterion::{criterion_group, criterion_main, Criterion};
+use sr_02_line::framebuffer::Framebuffer;
+use sr_02_line::{create_camera, initial_settings, load_scene, render_frame, Options, BACKGROUND_BOTTOM, BACKGROUND_TOP, SUPERSAMPLE};
+
+// One frame of the built-in scene at the default 800x600, supersampled the
+// same way the window and headless mode render it. Only render_frame is
+// timed, not downsampling, resolving or presenting. Run from the crate root so the
+// models and textures under assets/ are found.
+fn render_one_frame(c: &mut Criterion) {
+    let options = Options::default();
+    let scene = load_scene(&options);
+    let settings = initial_settings(&options);
+    let camera = create_camera();
+    let mut framebuffer = Framebuffer::new(800 * SUPERSAMPLE, 600 * SUPERSAMPLE);
+    framebuffer.set_background_gradient(BACKGROUND_TOP, BACKGROUND_BOTTOM);
+
+    c.bench_function("render_frame 800x600", |b| {
+        b.iter(|| render_frame(&mut framebuffer, &scene, &settings, 1.0, 1.0, &camera))
+    });
+}
+
+criterion_group! {
+    name = benches;
+    // A frame takes tens of milliseconds, so fewer samples keep a run short
+    config = Criterion::default().sample_size(20).measurement_time(Duration::from_secs(10));
+    targets = render_one_frame
+}
+criterion_main!(benches);
@@ -2,10 +2,31 @@
 use nalgebra_glm::Vec3;
 use std::f32::consts::PI;
 
+// A snapshot of where the camera is and what it looks at
+#[derive(Debug, Clone, Copy)]
+pub struct CameraView {
+    pub eye: Vec3,
+    pub center: Vec3,
+    pub up: Vec3,
+}
+
+// Smooth move between two views, advanced once per frame by `update`
+struct Transition {
+    from: CameraView,
+    to: CameraView,
+    frame: u32,
+    frames: u32,
+}
+
 pub struct Camera {
     pub eye: Vec3,
     pub center: Vec3,
-    pub up: Vec3
+    pub up: Vec3,
+    initial: CameraView,
+    transition: Option<Transition>,
+    // Closest and farthest `zoom` may put the eye from the center
+    min_distance: f32,
+    max_distance: f32,
 }
 
 impl Camera {
@@ -13,10 +34,57 @@ impl Camera {
         Camera {
             eye,
             center,
-            up
+            up,
+            initial: CameraView { eye, center, up },
+            transition: None,
+            min_distance: 0.0,
+            max_distance: f32::INFINITY,
         }
     }
 
+    pub fn view(&self) -> CameraView {
+        CameraView { eye: self.eye, center: self.center, up: self.up }
+    }
+
+    pub fn set_view(&mut self, view: CameraView) {
+        self.eye = view.eye;
+        self.center = view.center;
+        self.up = view.up;
+    }
+
+    // Jumps straight back to the view the camera was created with
+    pub fn reset(&mut self) {
+        self.transition = None;
+        self.set_view(self.initial);
+    }
+
+    // Starts moving towards `view`, arriving after `frames` calls to `update`
+    pub fn transition_to(&mut self, view: CameraView, frames: u32) {
+        self.transition = Some(Transition { from: self.view(), to: view, frame: 0, frames: frames.max(1) });
+    }
+
+    // Advances a running transition by one frame. Eye and center move in a
+    // straight line with smoothstep easing; the up vector is slerped so it
+    // stays unit length on the way.
+    pub fn update(&mut self) {
+        let Some(transition) = self.transition.as_mut() else {
+            return;
+        };
+        transition.frame += 1;
+        let t = transition.frame as f32 / transition.frames as f32;
+        let eased = t * t * (3.0 - 2.0 * t);
+        let (from, to) = (transition.from, transition.to);
+        if transition.frame >= transition.frames {
+            self.transition = None;
+        }
+
+        self.set_view(CameraView {
+            eye: from.eye.lerp(&to.eye, eased),
+            center: from.center.lerp(&to.center, eased),
+            up: slerp(&from.up, &to.up, eased),
+        });
+    }
+
     pub fn base_change(&self, vector: &Vec3) -> Vec3 {
         let forward = (self.center - self.eye).normalize();
         let right = forward.cross(&self.up).normalize();
@@ -24,7 +92,7 @@ impl Camera {
 
         let rotated = vector.x * right + vector.y * up - vector.z * forward;
 
-        return rotated.normalize();
+        rotated.normalize()
     }
 
     pub fn orbit(&mut self, delta_yaw: f32, delta_pitch: f32) {
@@ -46,9 +114,153 @@ impl Camera {
 
         self.eye = new_eye;
     }
-    pub fn zoom(&mut self, amount: f32) {
-        let direction = (self.center - self.eye).normalize();
-        self.eye += direction * amount;
+    pub fn set_zoom_limits(&mut self, min_distance: f32, max_distance: f32) {
+        self.min_distance = min_distance;
+        self.max_distance = max_distance.max(min_distance);
+    }
+
+    // Moves the eye `amount` towards the center (away when negative), keeping
+    // its distance within the zoom limits. Moving in, it also stops on the
+    // surface of the first `obstacles` sphere (center, radius) in the way;
+    // spheres the eye is already inside are ignored so it can't get stuck.
+    pub fn zoom(&mut self, amount: f32, obstacles: &[(Vec3, f32)]) {
+        let offset = self.center - self.eye;
+        let distance = offset.magnitude();
+        if distance <= f32::EPSILON {
+            return;
+        }
+        let direction = offset / distance;
+
+        let mut travel = distance - (distance - amount).clamp(self.min_distance, self.max_distance);
+        if travel > 0.0 {
+            for (center, radius) in obstacles {
+                if let Some(hit) = ray_sphere_entry(&self.eye, &direction, center, *radius) {
+                    travel = travel.min(hit);
+                }
+            }
+        }
+        self.eye += direction * travel;
+    }
+
+    fn forward(&self) -> Vec3 {
+        (self.center - self.eye).normalize()
+    }
+
+    // The free-fly moves translate eye and center together, keeping the view direction
+    pub fn move_forward(&mut self, distance: f32) {
+        let offset = self.forward() * distance;
+        self.eye += offset;
+        self.center += offset;
+    }
+
+    pub fn move_right(&mut self, distance: f32) {
+        let offset = self.forward().cross(&self.up).normalize() * distance;
+        self.eye += offset;
+        self.center += offset;
+    }
+
+    pub fn move_up(&mut self, distance: f32) {
+        let offset = self.up.normalize() * distance;
+        self.eye += offset;
+        self.center += offset;
+    }
+
+    // Slides eye and center together across the screen plane: `dx` along the
+    // camera's right, `dy` along its up (re-orthogonalized against the view
+    // direction), so what's on screen shifts without rotating
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let forward = self.forward();
+        let right = forward.cross(&self.up).normalize();
+        let up = right.cross(&forward).normalize();
+        let offset = right * dx + up * dy;
+        self.eye += offset;
+        self.center += offset;
+    }
+
+    // Rotates the view direction around the eye, the opposite of orbit
+    pub fn look(&mut self, delta_yaw: f32, delta_pitch: f32) {
+        let direction = self.center - self.eye;
+        let distance = direction.magnitude();
+
+        let current_yaw = direction.z.atan2(direction.x);
+        let current_pitch = (direction.y / distance).asin();
+
+        let new_yaw = (current_yaw + delta_yaw) % (2.0 * PI);
+        let new_pitch = (current_pitch + delta_pitch).clamp(-PI / 2.0 + 0.1, PI / 2.0 - 0.1);
+
+        self.center = self.eye + Vec3::new(
+            distance * new_yaw.cos() * new_pitch.cos(),
+            distance * new_pitch.sin(),
+            distance * new_yaw.sin() * new_pitch.cos()
+        );
+    }
+}
+
+// Distance along a unit-length ray to where it enters a sphere, None when it
+// misses, the sphere is behind, or the origin is well inside. An origin
+// resting on the surface (as `zoom` leaves it) counts as outside, so it stays
+// blocked instead of slipping through on the next step.
+fn ray_sphere_entry(origin: &Vec3, direction: &Vec3, center: &Vec3, radius: f32) -> Option<f32> {
+    const SURFACE_TOLERANCE: f32 = 1e-3;
+    let to_origin = origin - center;
+    let b = to_origin.dot(direction);
+    if b >= 0.0 || to_origin.magnitude() < radius - SURFACE_TOLERANCE {
+        return None;
+    }
+    let discriminant = b * b - (to_origin.magnitude_squared() - radius * radius);
+    if discriminant < 0.0 {
+        return None;
     }
-    
-}
\ No newline at end of file
+    Some((-b - discriminant.sqrt()).max(0.0))
+}
+
+// Spherical interpolation between two directions. Nearly parallel inputs fall
+// back to a normalized lerp; exactly opposite ones rotate about an arbitrary
+// perpendicular axis.
+fn slerp(a: &Vec3, b: &Vec3, t: f32) -> Vec3 {
+    let (a, b) = (a.normalize(), b.normalize());
+    let cos_angle = a.dot(&b).clamp(-1.0, 1.0);
+    if cos_angle > 0.9995 {
+        return a.lerp(&b, t).normalize();
+    }
+    if cos_angle < -0.9995 {
+        let axis = if a.x.abs() < 0.9 { Vec3::x() } else { Vec3::y() };
+        let perpendicular = a.cross(&axis).normalize();
+        let angle = PI * t;
+        return a * angle.cos() + perpendicular * angle.sin();
+    }
+
+    let angle = cos_angle.acos();
+    (a * ((1.0 - t) * angle).sin() + b * (t * angle).sin()) / angle.sin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pan_moves_eye_and_center_together() {
+        let mut camera = Camera::new(Vec3::new(0.0, 3.0, 5.0), Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let (eye, center) = (camera.eye, camera.center);
+        camera.pan(0.7, -0.4);
+
+        let moved = camera.eye - eye;
+        assert!(moved.magnitude() > 0.5);
+        assert!((camera.center - center - moved).magnitude() < 1e-6);
+        // Across the screen plane only, so the view direction is unchanged
+        assert!(moved.dot(&(center - eye)).abs() < 1e-5);
+        assert!((camera.center - camera.eye - (center - eye)).magnitude() < 1e-6);
+    }
+
+    #[test]
+    fn zoom_stops_exactly_at_the_limits() {
+        let mut camera = Camera::new(Vec3::new(0.0, 0.0, 10.0), Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0));
+        camera.set_zoom_limits(2.0, 20.0);
+
+        camera.zoom(50.0, &[]);
+        assert_eq!(camera.eye, Vec3::new(0.0, 0.0, 2.0));
+
+        camera.zoom(-50.0, &[]);
+        assert_eq!(camera.eye, Vec3::new(0.0, 0.0, 20.0));
+    }
+}
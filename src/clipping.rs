@@ -0,0 +1,217 @@
+use nalgebra_glm::{Vec3, Vec4, Mat4};
+use crate::vertex::Vertex;
+
+// Clip-space planes in the order left, right, bottom, top, near, far.
+// A point is inside a plane when its signed distance is >= 0.
+const PLANE_COUNT: usize = 6;
+
+fn plane_distance(plane: usize, p: &Vec4) -> f32 {
+    match plane {
+        0 => p.w + p.x,
+        1 => p.w - p.x,
+        2 => p.w + p.y,
+        3 => p.w - p.y,
+        4 => p.w + p.z,
+        _ => p.w - p.z,
+    }
+}
+
+fn is_inside(p: &Vec4) -> bool {
+    (0..PLANE_COUNT).all(|plane| plane_distance(plane, p) >= 0.0)
+}
+
+pub fn triangle_inside(v1: &Vertex, v2: &Vertex, v3: &Vertex) -> bool {
+    is_inside(&v1.clip_position) && is_inside(&v2.clip_position) && is_inside(&v3.clip_position)
+}
+
+// Conservative bounding-sphere test against the view frustum. Each clip
+// plane is linear in clip space, so applying it to the columns of
+// `view_proj` gives the same plane in world space. Only rejects spheres that
+// are entirely outside one plane; ones straddling a corner can slip through.
+pub fn sphere_in_frustum(center: Vec3, radius: f32, view_proj: &Mat4) -> bool {
+    let point = Vec4::new(center.x, center.y, center.z, 1.0);
+    (0..PLANE_COUNT).all(|plane| {
+        let coefficients = Vec4::from_fn(|i, _| plane_distance(plane, &view_proj.column(i).into_owned()));
+        let normal_length = coefficients.xyz().magnitude();
+        normal_length <= f32::EPSILON || coefficients.dot(&point) / normal_length >= -radius
+    })
+}
+
+fn lerp_vertex(a: &Vertex, b: &Vertex, t: f32) -> Vertex {
+    Vertex {
+        position: a.position + (b.position - a.position) * t,
+        normal: a.normal + (b.normal - a.normal) * t,
+        tex_coords: a.tex_coords + (b.tex_coords - a.tex_coords) * t,
+        color: a.color.lerp(&b.color, t),
+        clip_position: a.clip_position + (b.clip_position - a.clip_position) * t,
+        transformed_position: a.transformed_position + (b.transformed_position - a.transformed_position) * t,
+        transformed_normal: a.transformed_normal + (b.transformed_normal - a.transformed_normal) * t,
+        world_position: a.world_position + (b.world_position - a.world_position) * t,
+    }
+}
+
+// One Sutherland-Hodgman pass against a single plane
+fn clip_polygon(polygon: &[Vertex], plane: usize) -> Vec<Vertex> {
+    let mut output = Vec::with_capacity(polygon.len() + 1);
+
+    for i in 0..polygon.len() {
+        let current = &polygon[i];
+        let next = &polygon[(i + 1) % polygon.len()];
+        let d_current = plane_distance(plane, &current.clip_position);
+        let d_next = plane_distance(plane, &next.clip_position);
+
+        if d_current >= 0.0 {
+            output.push(current.clone());
+        }
+        if (d_current >= 0.0) != (d_next >= 0.0) {
+            let t = d_current / (d_current - d_next);
+            output.push(lerp_vertex(current, next, t));
+        }
+    }
+
+    output
+}
+
+// Clips a triangle against the six clip-space planes and re-triangulates the
+// resulting convex polygon as a fan. Every vertex attribute is interpolated at
+// the new vertices. Returns an empty Vec when the triangle is fully outside.
+pub fn clip_triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex) -> Vec<[Vertex; 3]> {
+    if triangle_inside(v1, v2, v3) {
+        return vec![[v1.clone(), v2.clone(), v3.clone()]];
+    }
+
+    // Trivial reject: all three vertices outside the same plane, e.g. a
+    // triangle entirely behind the near plane
+    let outside = |plane: usize| {
+        [v1, v2, v3].iter().all(|v| plane_distance(plane, &v.clip_position) < 0.0)
+    };
+    if (0..PLANE_COUNT).any(outside) {
+        return Vec::new();
+    }
+
+    let mut polygon = vec![v1.clone(), v2.clone(), v3.clone()];
+    for plane in 0..PLANE_COUNT {
+        polygon = clip_polygon(&polygon, plane);
+        if polygon.len() < 3 {
+            return Vec::new();
+        }
+    }
+
+    (1..polygon.len() - 1)
+        .map(|i| [polygon[0].clone(), polygon[i].clone(), polygon[i + 1].clone()])
+        .collect()
+}
+
+// Clips a clip-space segment against the six planes, returning the visible
+// part or None when the segment is fully outside
+pub fn clip_line(a: Vec4, b: Vec4) -> Option<(Vec4, Vec4)> {
+    let (mut t_start, mut t_end) = (0.0_f32, 1.0_f32);
+
+    for plane in 0..PLANE_COUNT {
+        let d_a = plane_distance(plane, &a);
+        let d_b = plane_distance(plane, &b);
+
+        if d_a < 0.0 && d_b < 0.0 {
+            return None;
+        }
+        if d_a < 0.0 {
+            t_start = t_start.max(d_a / (d_a - d_b));
+        } else if d_b < 0.0 {
+            t_end = t_end.min(d_a / (d_a - d_b));
+        }
+    }
+
+    if t_start > t_end {
+        return None;
+    }
+
+    Some((a + (b - a) * t_start, a + (b - a) * t_end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::Vec2;
+
+    // A vertex at the given clip-space position whose UVs and world position
+    // copy it, so interpolated attributes can be checked against the position
+    fn clip_vertex(x: f32, y: f32, z: f32, w: f32) -> Vertex {
+        let mut vertex = Vertex::new(Vec3::new(x, y, z), Vec3::new(0.0, 0.0, 1.0), Vec2::new(x, y));
+        vertex.clip_position = Vec4::new(x, y, z, w);
+        vertex.world_position = Vec3::new(x, y, z);
+        vertex
+    }
+
+    // Clips a triangle with one corner at `outside`, beyond `plane`, and
+    // checks the result lies in the frustum with a vertex on that plane
+    fn assert_clips_against(plane: usize, outside: Vec3) {
+        let (v1, v2, v3) = (
+            clip_vertex(outside.x, outside.y, outside.z, 1.0),
+            clip_vertex(0.5, 0.5, 0.5, 1.0),
+            clip_vertex(-0.5, -0.5, -0.5, 1.0),
+        );
+        assert!(plane_distance(plane, &v1.clip_position) < 0.0);
+
+        let triangles = clip_triangle(&v1, &v2, &v3);
+        assert!(!triangles.is_empty());
+        let vertices: Vec<&Vertex> = triangles.iter().flatten().collect();
+        for vertex in &vertices {
+            let p = vertex.clip_position;
+            assert!((0..PLANE_COUNT).all(|plane| plane_distance(plane, &p) >= -1e-5), "{:?}", p);
+            assert!((vertex.tex_coords - Vec2::new(p.x, p.y)).magnitude() < 1e-5);
+            assert!((vertex.world_position - p.xyz()).magnitude() < 1e-5);
+        }
+        assert!(vertices.iter().any(|vertex| plane_distance(plane, &vertex.clip_position).abs() < 1e-5));
+    }
+
+    #[test]
+    fn clips_against_left_plane() {
+        assert_clips_against(0, Vec3::new(-3.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn clips_against_right_plane() {
+        assert_clips_against(1, Vec3::new(3.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn clips_against_bottom_plane() {
+        assert_clips_against(2, Vec3::new(0.0, -3.0, 0.0));
+    }
+
+    #[test]
+    fn clips_against_top_plane() {
+        assert_clips_against(3, Vec3::new(0.0, 3.0, 0.0));
+    }
+
+    #[test]
+    fn clips_against_near_plane() {
+        assert_clips_against(4, Vec3::new(0.0, 0.0, -3.0));
+    }
+
+    #[test]
+    fn clips_against_far_plane() {
+        assert_clips_against(5, Vec3::new(0.0, 0.0, 3.0));
+    }
+
+    #[test]
+    fn triangle_straddling_near_plane_stays_in_frustum() {
+        let projection = crate::create_perspective_matrix(800.0, 600.0, 60.0, 0.1, 100.0);
+        // View space, looking down -Z: the first corner is behind the eye
+        let [v1, v2, v3] = [Vec3::new(0.0, 0.0, 1.0), Vec3::new(-1.0, -0.5, -4.0), Vec3::new(1.0, 0.5, -4.0)]
+            .map(|corner| {
+                let clip = projection * Vec4::new(corner.x, corner.y, corner.z, 1.0);
+                clip_vertex(clip.x, clip.y, clip.z, clip.w)
+            });
+        assert!(v1.clip_position.w < 0.0);
+
+        let triangles = clip_triangle(&v1, &v2, &v3);
+        assert!(!triangles.is_empty());
+        for vertex in triangles.iter().flatten() {
+            let clip = vertex.clip_position;
+            assert!(clip.w > 0.0);
+            let ndc = clip.xyz() / clip.w;
+            assert!(ndc.iter().all(|value| value.abs() <= 1.0 + 1e-4), "{:?}", ndc);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt;
+use nalgebra_glm::Vec3;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
     r: u8,
     g: u8,
@@ -23,11 +24,73 @@ impl Color {
         Color { r: 0, g: 0, b: 0 }
     }
 
-    pub fn to_hex(&self) -> u32 {
+    pub fn to_hex(self) -> u32 {
         ((self.r as u32) << 16) | ((self.g as u32) << 8) | (self.b as u32)
     }
 
-    // Linear interpolation between two colors
+    // Channels as floats in [0, 1]
+    pub fn to_rgb(self) -> Vec3 {
+        Vec3::new(self.r as f32, self.g as f32, self.b as f32) / 255.0
+    }
+
+    // Inverse of `to_rgb`, clamping each channel to [0, 1]
+    pub fn from_rgb(rgb: &Vec3) -> Self {
+        let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Color { r: channel(rgb.x), g: channel(rgb.y), b: channel(rgb.z) }
+    }
+
+    // Hue in degrees [0, 360), saturation and value in [0, 1]. Grays report
+    // a hue of 0.
+    pub fn to_hsv(self) -> (f32, f32, f32) {
+        let r = self.r as f32 / 255.0;
+        let g = self.g as f32 / 255.0;
+        let b = self.b as f32 / 255.0;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+        (hue, saturation, max)
+    }
+
+    // Inverse of `to_hsv`. The hue wraps, so animated values can grow without
+    // bound; saturation and value are clamped to [0, 1].
+    pub fn from_hsv(h: f32, s: f32, v: f32) -> Self {
+        let h = h.rem_euclid(360.0);
+        let s = s.clamp(0.0, 1.0);
+        let v = v.clamp(0.0, 1.0);
+
+        let chroma = v * s;
+        let x = chroma * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+        let m = v - chroma;
+        let (r, g, b) = match (h / 60.0) as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+
+        Color {
+            r: ((r + m) * 255.0).round() as u8,
+            g: ((g + m) * 255.0).round() as u8,
+            b: ((b + m) * 255.0).round() as u8,
+        }
+    }
+
+    // Linear interpolation between two colors. `t` is clamped to [0, 1], so
+    // callers can pass raw noise values without wrapping the channels.
     pub fn lerp(&self, other: &Color, t: f32) -> Self {
         let t = t.clamp(0.0, 1.0);
         Color {
@@ -37,6 +100,42 @@ impl Color {
         }
     }
 
+    // Piecewise-linear ramp through `stops`, given as (position, color) in
+    // ascending order of position. Before the first stop and after the last
+    // one the end colors are held; no stops gives black.
+    pub fn gradient(stops: &[(f32, Color)], t: f32) -> Self {
+        let (Some(&(first_t, first)), Some(&(last_t, last))) = (stops.first(), stops.last()) else {
+            return Color::black();
+        };
+        if t <= first_t {
+            return first;
+        }
+        if t >= last_t {
+            return last;
+        }
+
+        stops.windows(2)
+            .find(|pair| t <= pair[1].0)
+            .map(|pair| {
+                let ((start_t, start), (end_t, end)) = (pair[0], pair[1]);
+                let span = end_t - start_t;
+                if span > 0.0 { start.lerp(&end, (t - start_t) / span) } else { end }
+            })
+            .unwrap_or(last)
+    }
+
+    // Source-over compositing of `self` on top of `background`, where alpha is
+    // the opacity of `self`
+    pub fn blend_over(&self, background: Color, alpha: f32) -> Color {
+        background.lerp(self, alpha)
+    }
+
+    // Same as `* factor`, but reads better where a contribution is being
+    // weighted before it's added on top of another color
+    pub fn scale(self, factor: f32) -> Color {
+        self * factor
+    }
+
     pub fn is_black(&self) -> bool {
         self.r == 0 && self.g == 0 && self.b == 0 
     }
@@ -62,9 +161,9 @@ impl Color {
     }
     
     pub fn blend_subtract(&self, blend: &Color) -> Color {
-        let r = (self.r as i16 - blend.r as i16).max(0).min(255) as u8;
-        let g = (self.g as i16 - blend.g as i16).max(0).min(255) as u8;
-        let b = (self.b as i16 - blend.b as i16).max(0).min(255) as u8;
+        let r = (self.r as i16 - blend.r as i16).clamp(0, 255) as u8;
+        let g = (self.g as i16 - blend.g as i16).clamp(0, 255) as u8;
+        let b = (self.b as i16 - blend.b as i16).clamp(0, 255) as u8;
 
         Color::new(r, g, b)
     }
@@ -72,6 +171,7 @@ impl Color {
 
 use std::ops::Add;
 
+// Per-channel addition that saturates at 255 instead of wrapping
 impl Add for Color {
     type Output = Color;
 
@@ -86,6 +186,8 @@ impl Add for Color {
 
 use std::ops::Mul;
 
+// Scales every channel, clamping the result to 0..=255; negative or NaN
+// factors produce black and large factors saturate at 255
 impl Mul<f32> for Color {
     type Output = Color;
 
@@ -103,3 +205,76 @@ impl fmt::Display for Color {
         write!(f, "Color(r: {}, g: {}, b: {})", self.r, self.g, self.b)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_saturates_at_255() {
+        let sum = Color::new(250, 128, 0) + Color::new(10, 127, 255);
+        assert_eq!(sum, Color::new(255, 255, 255));
+    }
+
+    #[test]
+    fn scale_by_half() {
+        assert_eq!(Color::new(200, 100, 51).scale(0.5), Color::new(100, 50, 25));
+    }
+
+    #[test]
+    fn overflowing_add_saturates() {
+        assert_eq!(Color::new(200, 200, 200) + Color::new(100, 100, 100), Color::new(255, 255, 255));
+    }
+
+    #[test]
+    fn negative_multiply_clamps_to_black() {
+        assert_eq!(Color::new(200, 100, 50) * -1.5, Color::black());
+    }
+
+    #[test]
+    fn lerp_clamps_t() {
+        let (black, white) = (Color::black(), Color::new(255, 255, 255));
+        assert_eq!(black.lerp(&white, -0.5), black);
+        assert_eq!(black.lerp(&white, 1.5), white);
+    }
+
+    // Every channel value 0..=255 appears in some color
+    fn color_spread() -> impl Iterator<Item = Color> {
+        (0..=255u32).map(|i| Color::new(i as u8, (i * 37 % 256) as u8, (i * 101 % 256) as u8))
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        for color in color_spread() {
+            assert_eq!(Color::from_hex(color.to_hex()), color);
+        }
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        for color in color_spread() {
+            let (h, s, v) = color.to_hsv();
+            assert_eq!(Color::from_hsv(h, s, v), color);
+        }
+    }
+
+    #[test]
+    fn gradient_at_stops_and_midpoints() {
+        let (red, green, blue) = (Color::new(255, 0, 0), Color::new(0, 255, 0), Color::new(0, 0, 255));
+        let stops = [(0.0, red), (0.5, green), (1.0, blue)];
+
+        assert_eq!(Color::gradient(&stops, 0.0), red);
+        assert_eq!(Color::gradient(&stops, 0.5), green);
+        assert_eq!(Color::gradient(&stops, 1.0), blue);
+        assert_eq!(Color::gradient(&stops, 0.25), Color::new(128, 128, 0));
+        assert_eq!(Color::gradient(&stops, 0.75), Color::new(0, 128, 128));
+        assert_eq!(Color::gradient(&stops, -1.0), red);
+        assert_eq!(Color::gradient(&stops, 2.0), blue);
+    }
+
+    #[test]
+    fn half_red_over_blue_is_purple() {
+        let purple = Color::new(255, 0, 0).blend_over(Color::new(0, 0, 255), 0.5);
+        assert_eq!(purple, Color::new(128, 0, 128));
+    }
+}
@@ -0,0 +1,66 @@
+use nalgebra_glm::Vec3;
+use fastnoise_lite::{FastNoiseLite, NoiseType};
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use std::f32::consts::PI;
+use crate::color::Color;
+
+// Low resolution equirectangular image of the sky, used for reflections
+pub struct Environment {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Environment {
+    // Builds a faint nebula with scattered stars on top. Generated once at
+    // startup, so the cost doesn't matter.
+    pub fn starfield(width: usize, height: usize, seed: u64) -> Self {
+        let mut noise = FastNoiseLite::with_seed(seed as i32);
+        noise.set_noise_type(Some(NoiseType::OpenSimplex2));
+
+        let space_color = Color::new(5, 5, 15);
+        let nebula_color = Color::new(70, 30, 90);
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let direction = Self::direction_at(
+                    (x as f32 + 0.5) / width as f32,
+                    (y as f32 + 0.5) / height as f32,
+                );
+                let density = noise.get_noise_3d(direction.x * 2.0, direction.y * 2.0, direction.z * 2.0);
+                pixels.push(space_color.lerp(&nebula_color, density));
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let star_count = width * height / 40;
+        for _ in 0..star_count {
+            let index = rng.gen_range(0..pixels.len());
+            let brightness = rng.gen_range(120..=255);
+            pixels[index] = Color::new(brightness, brightness, 255);
+        }
+
+        Environment { width, height, pixels }
+    }
+
+    // Inverse of the lookup in `sample`: u wraps around Y, v runs from +Y to -Y
+    fn direction_at(u: f32, v: f32) -> Vec3 {
+        let phi = (u - 0.5) * 2.0 * PI;
+        let theta = v * PI;
+        Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+    }
+
+    pub fn sample(&self, direction: &Vec3) -> Color {
+        let direction = direction.normalize();
+        let u = 0.5 + direction.z.atan2(direction.x) / (2.0 * PI);
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+
+        let x = ((u * self.width as f32) as usize).min(self.width - 1);
+        let y = ((v * self.height as f32) as usize).min(self.height - 1);
+
+        self.pixels[y * self.width + x]
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+// How often the reported numbers change. Averaging over a whole window keeps
+// the title readable instead of flickering every frame.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct FpsCounter {
+    frames: u32,
+    elapsed: Duration,
+    fps: f32,
+    frame_ms: f32,
+}
+
+impl FpsCounter {
+    pub fn new() -> Self {
+        FpsCounter {
+            frames: 0,
+            elapsed: Duration::ZERO,
+            fps: 0.0,
+            frame_ms: 0.0,
+        }
+    }
+
+    // Records one frame. Returns true when a new average was produced, which
+    // happens roughly once per REPORT_INTERVAL.
+    pub fn tick(&mut self, frame_time: Duration) -> bool {
+        self.frames += 1;
+        self.elapsed += frame_time;
+        if self.elapsed < REPORT_INTERVAL {
+            return false;
+        }
+
+        let seconds = self.elapsed.as_secs_f32();
+        self.fps = self.frames as f32 / seconds;
+        self.frame_ms = seconds * 1000.0 / self.frames as f32;
+        self.frames = 0;
+        self.elapsed = Duration::ZERO;
+        true
+    }
+
+    pub fn fps(&self) -> f32 {
+        self.fps
+    }
+
+    pub fn frame_ms(&self) -> f32 {
+        self.frame_ms
+    }
+}
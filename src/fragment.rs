@@ -2,24 +2,35 @@
 use nalgebra_glm::{Vec2, Vec3};
 use crate::color::Color;
 
+#[derive(Clone, Copy)]
 pub struct Fragment {
     pub position: Vec2,
     pub color: Color,
     pub depth: f32,
     pub normal: Vec3,
+    // Diffuse light term in [0, 1]. The rasterizer leaves it at 0 and
+    // `fragment_shader` fills it in from the interpolated normal.
     pub intensity: f32,
-    pub vertex_position: Vec3,
+    // Model-space position, before the model matrix: fixed to the surface
+    // however the body moves, so procedural noise sampled here doesn't swim
+    pub object_position: Vec3,
+    pub tex_coords: Vec2,
+    pub world_position: Vec3,
+    pub alpha: f32,
 }
 
 impl Fragment {
-    pub fn new(x: f32, y: f32, color: Color, depth: f32, normal: Vec3, intensity: f32, vertex_position: Vec3,) -> Self {
+    pub fn new(x: f32, y: f32, color: Color, depth: f32, normal: Vec3, object_position: Vec3) -> Self {
         Fragment {
             position: Vec2::new(x, y),
             color,
             depth,
             normal,
-            intensity,
-            vertex_position
+            intensity: 0.0,
+            object_position,
+            tex_coords: Vec2::zeros(),
+            world_position: object_position,
+            alpha: 1.0,
         }
     }
 }
\ No newline at end of file
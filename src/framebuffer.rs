@@ -1,11 +1,44 @@
+use crate::png;
+use crate::color::Color;
+use crate::font::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::tonemap::ToneMapping;
+use nalgebra_glm::Vec3;
+use std::ops::Range;
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 
+// Drawing goes into `hdr`, linear color where 1.0 is white but brighter
+// values are allowed. `resolve()` packs it into the back buffer, overlays go
+// on top of that, and `present()` swaps it with the front buffer, which is
+// the only one ever shown. The frame being built and the one on screen are
+// never the same memory.
 pub struct Framebuffer {
     pub width: usize,
     pub height: usize,
-    pub buffer: Vec<u32>,
+    back: Vec<u32>,
+    front: Vec<u32>,
+    pub hdr: Vec<Vec3>,
+    // Depth, like `hdr` and `ids`, belongs to the frame being drawn; the
+    // front buffer has none.
+    //
+    // Post-projection NDC z of the nearest opaque fragment: -1 on the near
+    // plane, 1 on the far one, smaller is nearer. Every writer (triangles,
+    // lines, debug lines) interpolates it linearly in screen space after the
+    // perspective divide, which is exact for NDC z. INFINITY where nothing
+    // has been drawn.
     pub zbuffer: Vec<f32>,
-    background_color: u32,
-    current_color: u32,
+    // Object id per pixel for picking: index + 1 of whatever drew the
+    // nearest opaque fragment, 0 where nothing did
+    pub ids: Vec<u32>,
+    // Background rows blend from `background_top` on the first row to
+    // `background_bottom` on the last; equal colors give a flat fill
+    background_top: u32,
+    background_bottom: u32,
+    current_color: Vec3,
+    current_id: u32,
+    gamma: f32,
+    tone_mapping: ToneMapping,
 }
 
 impl Framebuffer {
@@ -13,38 +46,428 @@ impl Framebuffer {
         Framebuffer {
             width,
             height,
-            buffer: vec![0; width * height],
+            back: vec![0; width * height],
+            front: vec![0; width * height],
+            hdr: vec![Vec3::zeros(); width * height],
             zbuffer: vec![f32::INFINITY; width * height],
-            background_color: 0x000000,
-            current_color: 0xFFFFFF
+            ids: vec![0; width * height],
+            background_top: 0x000000,
+            background_bottom: 0x000000,
+            current_color: Vec3::new(1.0, 1.0, 1.0),
+            current_id: 0,
+            gamma: 2.2,
+            tone_mapping: ToneMapping::Aces,
         }
     }
 
     pub fn clear(&mut self) {
-        for pixel in self.buffer.iter_mut() {
-            *pixel = self.background_color;
-        }
+        self.fill_background();
         for depth in self.zbuffer.iter_mut() {
             *depth = f32::INFINITY;
         }
+        self.ids.fill(0);
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.back = vec![self.background_top; width * height];
+        self.front = vec![self.background_top; width * height];
+        self.hdr = vec![Vec3::zeros(); width * height];
+        self.zbuffer = vec![f32::INFINITY; width * height];
+        self.ids = vec![0; width * height];
+        self.fill_background();
+    }
+
+    // Resets the pixels in the rectangle to the background and forgets what
+    // was picked there, leaving depth alone. The rectangle is clamped to the
+    // buffer, so it may hang off any edge.
+    pub fn clear_rect(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        let (columns, rows) = self.clamp_rect(x, y, width, height);
+        for y in rows {
+            let background = self.background_row(y);
+            let row = y * self.width;
+            self.hdr[row + columns.start..row + columns.end].fill(background);
+            self.ids[row + columns.start..row + columns.end].fill(0);
+        }
+    }
+
+    // Resets depth in the rectangle to INFINITY, clamped like `clear_rect`
+    pub fn clear_depth_rect(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        let (columns, rows) = self.clamp_rect(x, y, width, height);
+        for y in rows {
+            let row = y * self.width;
+            self.zbuffer[row + columns.start..row + columns.end].fill(f32::INFINITY);
+        }
+    }
+
+    fn clamp_rect(&self, x: usize, y: usize, width: usize, height: usize) -> (Range<usize>, Range<usize>) {
+        let columns = x.min(self.width)..x.saturating_add(width).min(self.width);
+        let rows = y.min(self.height)..y.saturating_add(height).min(self.height);
+        (columns, rows)
+    }
+
+    fn fill_background(&mut self) {
+        for y in 0..self.height {
+            let background = self.background_row(y);
+            self.hdr[y * self.width..(y + 1) * self.width].fill(background);
+        }
     }
 
-    pub fn point(&mut self, x: usize, y: usize, depth: f32) {
+    fn background_row(&self, y: usize) -> Vec3 {
+        let top = Color::from_hex(self.background_top).to_rgb();
+        let bottom = Color::from_hex(self.background_bottom).to_rgb();
+        let last_row = self.height.saturating_sub(1).max(1) as f32;
+        top.lerp(&bottom, y as f32 / last_row)
+    }
+
+    // Packs the finished frame into the back buffer: tone-maps each channel
+    // into [0, 1], then gamma-encodes it by raising it to 1 / gamma. Call once
+    // per frame, after all drawing and before the frame is saved or presented.
+    pub fn resolve(&mut self) {
+        // Finer steps than the 8-bit output: gamma stretches the dark end, so
+        // 256 linear levels would band visibly in the background gradient
+        const LEVELS: usize = 4096;
+        let mut table = [0u8; LEVELS];
+        for (value, entry) in table.iter_mut().enumerate() {
+            let encoded = (value as f32 / (LEVELS - 1) as f32).powf(1.0 / self.gamma) * 255.0;
+            *entry = encoded.round().clamp(0.0, 255.0) as u8;
+        }
+
+        let tone_mapping = self.tone_mapping;
+        let encode = |value: f32| table[(tone_mapping.map(value) * (LEVELS - 1) as f32).round() as usize] as u32;
+        for (pixel, color) in self.back.iter_mut().zip(&self.hdr) {
+            *pixel = (encode(color.x) << 16) | (encode(color.y) << 8) | encode(color.z);
+        }
+    }
+
+    // Makes the resolved back buffer, overlays included, the front one. The
+    // old front becomes the back buffer and is overwritten by the next
+    // `resolve()`.
+    pub fn present(&mut self) {
+        std::mem::swap(&mut self.back, &mut self.front);
+    }
+
+    // Packed 0xRRGGBB pixels of the last presented frame, for the window
+    pub fn front(&self) -> &[u32] {
+        &self.front
+    }
+
+    // Box-filters each `factor` x `factor` block of this buffer into one pixel
+    // of `target`, which must be exactly `factor` times smaller on each axis.
+    // Averages the linear HDR values, before any tone mapping.
+    pub fn downsample(&self, factor: usize, target: &mut Framebuffer) {
+        let samples = (factor * factor) as f32;
+        for y in 0..target.height {
+            for x in 0..target.width {
+                let mut sum = Vec3::zeros();
+                for sy in 0..factor {
+                    let row = (y * factor + sy) * self.width;
+                    for sx in 0..factor {
+                        sum += self.hdr[row + x * factor + sx];
+                    }
+                }
+                target.hdr[y * target.width + x] = sum / samples;
+            }
+        }
+    }
+
+    // Makes bright pixels glow into their surroundings. The part of each pixel
+    // whose luminance is above `threshold` is blurred with a separable
+    // Gaussian (horizontal then vertical pass) and added back, scaled by
+    // `intensity`. Works on the linear HDR values, so it should run after
+    // `downsample` and before `resolve` tone-maps the result.
+    pub fn apply_bloom(&mut self, threshold: f32, intensity: f32) {
+        if intensity <= 0.0 || self.width == 0 || self.height == 0 {
+            return;
+        }
+
+        let bright: Vec<Vec3> = self.hdr.iter().map(|color| {
+            let luminance = color.dot(&Vec3::new(0.2126, 0.7152, 0.0722));
+            if luminance > threshold {
+                color * ((luminance - threshold) / luminance)
+            } else {
+                Vec3::zeros()
+            }
+        }).collect();
+
+        // The glow keeps the same size on screen whatever the resolution
+        let sigma = (self.height as f32 / 150.0).max(1.0);
+        let kernel = gaussian_kernel(sigma);
+        let radius = (kernel.len() / 2) as isize;
+        let (width, height) = (self.width as isize, self.height as isize);
+
+        let mut horizontal = vec![Vec3::zeros(); bright.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = Vec3::zeros();
+                for (offset, weight) in (-radius..=radius).zip(&kernel) {
+                    let sx = (x + offset).clamp(0, width - 1);
+                    sum += bright[(y * width + sx) as usize] * *weight;
+                }
+                horizontal[(y * width + x) as usize] = sum;
+            }
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = Vec3::zeros();
+                for (offset, weight) in (-radius..=radius).zip(&kernel) {
+                    let sy = (y + offset).clamp(0, height - 1);
+                    sum += horizontal[(sy * width + x) as usize] * *weight;
+                }
+                self.hdr[(y * width + x) as usize] += sum * intensity;
+            }
+        }
+    }
+
+    // Scatters stars over the cleared buffer. `density` is the fraction of
+    // `star_size` x `star_size` cells that get a star; the same seed always
+    // gives the same sky, so the stars don't flicker between frames. The depth
+    // buffer is left at the far plane, so any planet fragment draws over them.
+    // A `star_size` of 0 draws nothing.
+    pub fn draw_starfield(&mut self, seed: u64, density: f32, star_size: usize) {
+        if star_size == 0 {
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (columns, rows) = (self.width / star_size, self.height / star_size);
+        if columns == 0 || rows == 0 {
+            return;
+        }
+
+        let star_count = ((columns * rows) as f32 * density.clamp(0.0, 1.0)) as usize;
+        for _ in 0..star_count {
+            let column = rng.gen_range(0..columns);
+            let row = rng.gen_range(0..rows);
+            let brightness: u32 = rng.gen_range(80..=255);
+            let blue = if rng.gen_bool(0.3) { 255 } else { brightness };
+            let color = Color::from_hex((brightness << 16) | (brightness << 8) | blue).to_rgb();
+
+            for y in row * star_size..(row + 1) * star_size {
+                let start = y * self.width + column * star_size;
+                self.hdr[start..start + star_size].fill(color);
+            }
+        }
+    }
+
+    // Returns whether the point passed the bounds and depth tests and was written
+    pub fn point(&mut self, x: usize, y: usize, depth: f32) -> bool {
         if x < self.width && y < self.height {
             let index = y * self.width + x;
 
             if self.zbuffer[index] > depth {
-                self.buffer[index] = self.current_color;
+                self.hdr[index] = self.current_color;
                 self.zbuffer[index] = depth;
+                // Unpickable overlays like the debug lines leave the id below
+                if self.current_id != 0 {
+                    self.ids[index] = self.current_id;
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    // Composites `color` over the stored pixel with the given opacity. The
+    // depth test still applies, but translucent fragments don't write depth so
+    // anything drawn later behind them still shows through.
+    pub fn blend_point(&mut self, x: usize, y: usize, depth: f32, color: Color, alpha: f32) -> bool {
+        if x < self.width && y < self.height {
+            let index = y * self.width + x;
+
+            if self.zbuffer[index] > depth {
+                let alpha = alpha.clamp(0.0, 1.0);
+                self.hdr[index] = self.hdr[index] * (1.0 - alpha) + color.to_rgb() * alpha;
+                return true;
+            }
+        }
+        false
+    }
+
+    // Overlay drawing below goes straight into the packed back buffer,
+    // skipping depth, tone mapping and gamma, so it has to happen between
+    // `resolve()` and `present()`.
+    // Pixels outside the buffer are dropped.
+    fn overlay_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.back[y as usize * self.width + x as usize] = color;
+        }
+    }
+
+    // Writes `text` in the built-in 5x7 font with its top-left corner at
+    // (x, y), one pixel of spacing between glyphs and lines
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, color: u32) {
+        let (mut pen_x, mut pen_y) = (x, y);
+        for character in text.chars() {
+            if character == '\n' {
+                pen_x = x;
+                pen_y += GLYPH_HEIGHT as i32 + 1;
+                continue;
             }
+            for (row, bits) in font::glyph(character).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (0x10 >> column) != 0 {
+                        self.overlay_pixel(pen_x + column as i32, pen_y + row as i32, color);
+                    }
+                }
+            }
+            pen_x += GLYPH_WIDTH as i32 + 1;
+        }
+    }
+
+    // Plus sign at the center of the screen, `size` pixels from the middle
+    // to each tip, leaving the middle 3x3 pixels open
+    pub fn draw_crosshair(&mut self, size: i32, color: u32) {
+        let (center_x, center_y) = (self.width as i32 / 2, self.height as i32 / 2);
+        for offset in 2..=size {
+            self.overlay_pixel(center_x + offset, center_y, color);
+            self.overlay_pixel(center_x - offset, center_y, color);
+            self.overlay_pixel(center_x, center_y + offset, color);
+            self.overlay_pixel(center_x, center_y - offset, color);
+        }
+    }
+
+    // Packed 0xRRGGBB pixel of the frame on screen, overlays included; None
+    // out of bounds
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<u32> {
+        if x < self.width && y < self.height {
+            Some(self.front[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    // Stored depth at a pixel, INFINITY where nothing has been drawn or out of bounds
+    pub fn get_depth(&self, x: usize, y: usize) -> f32 {
+        if x < self.width && y < self.height {
+            self.zbuffer[y * self.width + x]
+        } else {
+            f32::INFINITY
         }
     }
 
+    // Index of the object whose opaque surface is nearest at a pixel. Translucent
+    // fragments don't count, so clicking through a ring finds what's behind it.
+    pub fn pick(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            self.ids[y * self.width + x].checked_sub(1).map(|id| id as usize)
+        } else {
+            None
+        }
+    }
+
+    // Saves the back buffer, so call it after `resolve()`. Overlays drawn
+    // before it end up in the image. Rows are stored top first, same as PNG,
+    // so no flip is needed.
+    pub fn save_png(&self, path: &str) -> std::io::Result<()> {
+        let mut rows = Vec::with_capacity(self.width * self.height * 3);
+        for pixel in &self.back {
+            rows.push((pixel >> 16) as u8);
+            rows.push((pixel >> 8) as u8);
+            rows.push(*pixel as u8);
+        }
+
+        png::write_rgb(path, self.width, self.height, &rows)
+    }
+
+    // Flat background, the same as a gradient whose ends match
     pub fn set_background_color(&mut self, color: u32) {
-        self.background_color = color;
+        self.set_background_gradient(color, color);
+    }
+
+    // Vertical gradient from `top` on the first row to `bottom` on the last,
+    // redrawn by every `clear()`
+    pub fn set_background_gradient(&mut self, top: u32, bottom: u32) {
+        self.background_top = top;
+        self.background_bottom = bottom;
+    }
+
+    // 1.0 turns gamma encoding off
+    pub fn set_gamma(&mut self, gamma: f32) {
+        self.gamma = gamma;
+    }
+
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    // Object written into the id buffer by the next points. With None they
+    // leave the id buffer alone, so overlays don't hide what's under them.
+    pub fn set_current_id(&mut self, id: Option<usize>) {
+        self.current_id = id.map_or(0, |id| id as u32 + 1);
     }
 
     pub fn set_current_color(&mut self, color: u32) {
-        self.current_color = color;
+        self.current_color = Color::from_hex(color).to_rgb();
+    }
+
+    // Linear color for the next points, allowed to go past 1.0
+    pub fn set_current_radiance(&mut self, radiance: Vec3) {
+        self.current_color = radiance;
+    }
+}
+
+// Normalized 1D Gaussian weights covering three standard deviations each side
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius = (sigma * 3.0).ceil() as i32;
+    let weights: Vec<f32> = (-radius..=radius)
+        .map(|offset| (-(offset * offset) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+    weights.into_iter().map(|weight| weight / total).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_keeps_background_and_clears_depth_and_ids() {
+        let mut framebuffer = Framebuffer::new(8, 6);
+        framebuffer.set_background_color(0x336699);
+        framebuffer.clear();
+        framebuffer.set_current_id(Some(3));
+        framebuffer.point(2, 2, 0.5);
+
+        framebuffer.resize(12, 10);
+        let background = Color::from_hex(0x336699).to_rgb();
+        assert_eq!(framebuffer.hdr.len(), 12 * 10);
+        assert!(framebuffer.hdr.iter().all(|pixel| (pixel - background).magnitude() < 1e-6));
+        assert!(framebuffer.zbuffer.iter().all(|&depth| depth == f32::INFINITY));
+        assert!(framebuffer.ids.iter().all(|&id| id == 0));
+    }
+
+    #[test]
+    fn clear_rect_leaves_outside_pixels_untouched() {
+        let mut framebuffer = Framebuffer::new(8, 6);
+        framebuffer.clear();
+        framebuffer.set_current_color(0xFF0000);
+        framebuffer.set_current_id(Some(0));
+        for y in 0..6 {
+            for x in 0..8 {
+                framebuffer.point(x, y, 0.5);
+            }
+        }
+
+        framebuffer.clear_rect(2, 1, 3, 2);
+        let red = Color::from_hex(0xFF0000).to_rgb();
+        for y in 0..6 {
+            for x in 0..8 {
+                let index = y * 8 + x;
+                let inside = (2..5).contains(&x) && (1..3).contains(&y);
+                assert_eq!(framebuffer.hdr[index] == red, !inside, "pixel ({}, {})", x, y);
+                assert_eq!(framebuffer.ids[index] == 1, !inside, "pixel ({}, {})", x, y);
+                assert_eq!(framebuffer.zbuffer[index], 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_star_size_draws_nothing() {
+        let mut framebuffer = Framebuffer::new(8, 6);
+        framebuffer.clear();
+        framebuffer.draw_starfield(7, 1.0, 0);
+        assert!(framebuffer.hdr.iter().all(|pixel| *pixel == Vec3::zeros()));
     }
 }
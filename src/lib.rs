@@ -0,0 +1,2077 @@
+use nalgebra_glm::{Vec3, Vec4, Mat4, look_at, perspective, ortho};
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::f32::consts::PI;
+use std::cell::Cell;
+
+pub mod framebuffer;
+mod triangle;
+mod vertex;
+mod obj;
+mod color;
+mod fragment;
+mod shaders;
+pub mod camera;
+mod clipping;
+mod line;
+mod environment;
+mod material;
+mod png;
+mod primitives;
+mod fps;
+mod texture;
+mod tonemap;
+mod font;
+mod scene;
+mod recording;
+
+use framebuffer::Framebuffer;
+use vertex::Vertex;
+use fragment::Fragment;
+use obj::Obj;
+use camera::{Camera, CameraView};
+use color::Color;
+use triangle::{triangle, is_front_facing, face_normal};
+use line::line;
+use environment::Environment;
+use material::Material;
+use fps::FpsCounter;
+use texture::Texture;
+use tonemap::ToneMapping;
+use scene::PlanetDesc;
+use recording::{Keyframe, Recording};
+use shaders::{vertex_shader, screen_mapping, fragment_shader, fragment_alpha, fog_blend, view_distance, NoiseParams, ATMOSPHERE_SCALE, SHADER_COUNT};
+use clipping::{clip_triangle, clip_line, triangle_inside, sphere_in_frustum};
+use fastnoise_lite::{FastNoiseLite, FractalType, NoiseType};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+pub struct Uniforms<'a> {
+    model_matrix: Mat4,
+    view_matrix: Mat4,
+    projection_matrix: Mat4,
+    viewport_matrix: Mat4,
+    // Whole animation ticks elapsed, kept for code that still wants the old
+    // frame counter. New code should use `seconds` or `animation_time()`.
+    #[allow(dead_code)]
+    time: u32,
+    seconds: f32,
+    noise: &'a FastNoiseLite,
+    fractal_noise: &'a FastNoiseLite,
+    material: Material,
+    ambient_color: Color,
+    ambient: f32,
+    light_dir: Vec3,
+    sun_pos: Vec3,
+    sun_lighting: bool,
+    light_pos: Vec3,
+    light_color: Vec3,
+    dither_strength: f32,
+    camera_position: Vec3,
+    environment: &'a Environment,
+    texture: Option<&'a Texture>,
+    // Bounding sphere (center, radius) of every body this frame, sun first,
+    // and which of them is being drawn
+    shadow_casters: &'a [(Vec3, f32)],
+    body_index: usize,
+    cull_backfaces: bool,
+    wireframe: bool,
+    // Filled as usual, then every triangle's edges on top
+    wireframe_overlay: bool,
+    noise_params: NoiseParams,
+    // One geometric normal per triangle instead of interpolated vertex normals
+    flat_shading: bool,
+    debug_depth: bool,
+    // Clip plane distances of the current projection
+    near: f32,
+    far: f32,
+    fog_color: Vec3,
+    fog_density: f32,
+    // Transform and shade on rayon's thread pool. Only has an effect with the `parallel`
+    // feature; the output is identical either way.
+    #[cfg_attr(not(feature = "parallel"), allow(dead_code))]
+    parallel: bool,
+}
+
+#[cfg(test)]
+impl Uniforms<'static> {
+    // A body at the origin, unscaled and unrotated, drawn into a width x
+    // height framebuffer from create_camera() with the default settings and
+    // the sun off to +X. The noise and environment are leaked so tests can
+    // hold the uniforms for as long as they like.
+    pub(crate) fn for_test(width: f32, height: f32) -> Self {
+        let settings = RenderSettings::new();
+        let camera = create_camera();
+        Uniforms {
+            model_matrix: Mat4::identity(),
+            view_matrix: create_view_matrix(camera.eye, camera.center, camera.up),
+            projection_matrix: create_perspective_matrix(width, height, settings.fov, settings.near, settings.far),
+            viewport_matrix: create_viewport_matrix(width, height),
+            time: 0,
+            seconds: 0.0,
+            noise: Box::leak(Box::new(create_cloud_noise_with_seed(1337))),
+            fractal_noise: Box::leak(Box::new(create_fractal_noise(1337, 4, 2.0, 0.5))),
+            material: Material::matte(Color::new(255, 255, 255)),
+            ambient_color: Color::black(),
+            ambient: AMBIENT_LIGHT,
+            light_dir: settings.light_dir,
+            sun_pos: Vec3::new(10.0, 0.0, 0.0),
+            sun_lighting: settings.sun_lighting,
+            light_pos: settings.light_pos,
+            light_color: settings.light_color,
+            dither_strength: DITHER_STRENGTH,
+            camera_position: camera.eye,
+            environment: Box::leak(Box::new(Environment::starfield(16, 8, 42))),
+            texture: None,
+            shadow_casters: &[],
+            body_index: 0,
+            cull_backfaces: settings.cull_backfaces,
+            wireframe: settings.wireframe,
+            wireframe_overlay: settings.wireframe_overlay,
+            noise_params: settings.noise_params,
+            flat_shading: settings.flat_shading,
+            debug_depth: settings.debug_depth,
+            near: settings.near,
+            far: settings.far,
+            fog_color: Vec3::zeros(),
+            fog_density: 0.0,
+            parallel: settings.parallel,
+        }
+    }
+}
+
+// Fraction of a lit body's albedo still visible on the side facing away
+// from the light
+const AMBIENT_LIGHT: f32 = 0.15;
+
+// Point light, moved with the number keys. The strength is the intensity at
+// distance 0 before the 1 / (1 + d²) falloff.
+const POINT_LIGHT_STRENGTH: f32 = 3.0;
+const POINT_LIGHT_STEP: f32 = 0.05;
+
+// Supersampling antialiasing: the scene is rendered at SUPERSAMPLE times the
+// window size on each axis and box-filtered down for display. Shading and
+// rasterization cost grow with the square (4x the fragments at 2, 16x at 4).
+pub const SUPERSAMPLE: usize = 2;
+
+// Backdrop gradient, near black at the top of the screen to a dark navy at the
+// bottom. Fog fades towards the color halfway between the two.
+pub const BACKGROUND_TOP: u32 = 0x010103;
+pub const BACKGROUND_BOTTOM: u32 = 0x03040c;
+
+// Fog thickness per world unit: a planet at the camera's starting distance
+// of about 6 units keeps 40% of its color, and nothing survives past ~30
+const FOG_DENSITY: f32 = 0.15;
+
+// Bloom: only luminance above 1.0 (brighter than white, so just emissive
+// surfaces like the sun and lava) glows, added back at 60% strength
+const BLOOM_THRESHOLD: f32 = 1.0;
+const BLOOM_INTENSITY: f32 = 0.6;
+
+// Background stars. The seed is fixed so the sky is identical every frame.
+const STAR_SEED: u64 = 7;
+const STAR_DENSITY: f32 = 0.002;
+
+// The shaders and orbits were tuned against a counter that advanced once per
+// ~16 ms frame, so real seconds are converted back into those ticks.
+const TICKS_PER_SECOND: f32 = 60.0;
+
+impl Uniforms<'_> {
+    // Scene clock in animation ticks, advancing at the same pace regardless
+    // of the achieved frame rate.
+    pub fn animation_time(&self) -> f32 {
+        self.seconds * TICKS_PER_SECOND
+    }
+}
+
+// Per-frame pipeline counters. Only collected when built with the `stats`
+// feature; otherwise every field stays at zero.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderStats {
+    pub triangles_submitted: usize,
+    pub triangles_culled: usize,
+    pub triangles_clipped: usize,
+    pub fragments_shaded: usize,
+    pub fragments_depth_failed: usize,
+    pub objects_culled: usize,
+}
+
+fn create_cloud_noise_with_seed(seed: i32) -> FastNoiseLite {
+    let mut noise = FastNoiseLite::with_seed(seed);
+    noise.set_noise_type(Some(NoiseType::OpenSimplex2));
+    noise
+}
+
+// Fractal Brownian motion: `octaves` layers of OpenSimplex2, each one
+// `lacunarity` times finer and `gain` times weaker than the last
+fn create_fractal_noise(seed: i32, octaves: i32, lacunarity: f32, gain: f32) -> FastNoiseLite {
+    let mut noise = FastNoiseLite::with_seed(seed);
+    noise.set_noise_type(Some(NoiseType::OpenSimplex2));
+    noise.set_fractal_type(Some(FractalType::FBm));
+    noise.set_fractal_octaves(Some(octaves));
+    noise.set_fractal_lacunarity(Some(lacunarity));
+    noise.set_fractal_gain(Some(gain));
+    noise
+}
+
+// Bodies drawn with the gaseous shader get a Saturn-style ring
+const RINGED_SHADER_INDEX: usize = 7;
+const RING_SHADER_INDEX: usize = 9;
+const RING_TILT: Vec3 = Vec3::new(0.45, 0.0, 0.25);
+const RING_INNER_RADIUS: f32 = 0.65;
+const RING_OUTER_RADIUS: f32 = 1.1;
+// Earth-like bodies get a translucent atmosphere shell, ATMOSPHERE_SCALE
+// times their size, drawn with the atmosphere shader
+const ATMOSPHERIC_SHADER_INDEX: usize = 6;
+const ATMOSPHERE_SHADER_INDEX: usize = 14;
+
+// Rayon doesn't split the fragments of a draw into pieces smaller than
+// this; shading fewer costs less than handing them to another thread
+#[cfg(feature = "parallel")]
+const MIN_PARALLEL_CHUNK: usize = 2048;
+
+const ORTHO_HALF_HEIGHT: f32 = 4.0;
+
+// Width of the noise range around each band threshold that gets dithered
+const DITHER_STRENGTH: f32 = 0.08;
+// Wireframe overlay edges are pulled this far towards the camera in NDC
+// depth so they win the depth test against the surface they outline
+const WIREFRAME_DEPTH_BIAS: f32 = 1e-4;
+const WIREFRAME_OVERLAY_COLOR: u32 = 0x28FF78;
+
+const FLY_SPEED: f32 = 0.1;
+// Overlay drawn on the window only: the free-fly crosshair and, with Tab,
+// the camera position in the top-left corner
+const HUD_COLOR: u32 = 0xE0E0E0;
+const HUD_MARGIN: i32 = 8;
+const CROSSHAIR_SIZE: i32 = 6;
+const MOUSE_LOOK_SENSITIVITY: f32 = 0.005;
+// Orbit mode: radians per pixel dragged with the left button, and zoom per
+// scroll wheel step
+const MOUSE_ORBIT_SENSITIVITY: f32 = 0.01;
+const SCROLL_ZOOM_SPEED: f32 = 0.1;
+// Zoom keeps the eye between these distances from the target, and at least
+// CAMERA_CLEARANCE beyond any body's surface so the near plane doesn't cut it
+const CAMERA_MIN_DISTANCE: f32 = 1.0;
+const CAMERA_MAX_DISTANCE: f32 = 30.0;
+const CAMERA_CLEARANCE: f32 = 0.15;
+// Middle-drag pan, as a fraction of the distance to the target per pixel so
+// the scene follows the cursor at any zoom
+const MOUSE_PAN_SENSITIVITY: f32 = 0.0015;
+
+// [ and ] change how fast bodies move along their orbits, in steps of
+// ORBIT_SPEED_STEP between 0 (frozen) and MAX_ORBIT_SPEED_SCALE
+const ORBIT_SPEED_STEP: f32 = 0.25;
+const MAX_ORBIT_SPEED_SCALE: f32 = 5.0;
+
+// +/- scale the shaders' noise zoom by this per press (animation speed with
+// Shift held)
+const NOISE_PARAM_STEP: f32 = 1.1;
+
+// < and > (Shift with , and .) narrow and widen the vertical field of view by
+// FOV_STEP degrees, between MIN_FOV and MAX_FOV
+const DEFAULT_FOV: f32 = 60.0;
+const FOV_STEP: f32 = 5.0;
+const MIN_FOV: f32 = 10.0;
+const MAX_FOV: f32 = 120.0;
+
+// Number keys that hold saved views, and how long recalling one takes
+const VIEW_SLOT_KEYS: [Key; 9] = [
+    Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5,
+    Key::Key6, Key::Key7, Key::Key8, Key::Key9,
+];
+const VIEW_TRANSITION_FRAMES: u32 = 20;
+
+// F9 writes here and F10 plays it back; --playback can render it headless
+const RECORDING_PATH: &str = "recording.bin";
+
+const GRID_HALF_LINES: i32 = 6;
+const GRID_SPACING: f32 = 1.0;
+// Long enough to stick out of the sun at the origin
+const AXES_LENGTH: f32 = 1.5;
+const ORBIT_PATH_SEGMENTS: usize = 128;
+
+// Minimum on-screen radius in pixels for each level of detail past the full
+// mesh; anything smaller than the last entry uses the coarsest mesh
+const LOD_SCREEN_RADII: [f32; 2] = [30.0, 12.0];
+// A body only changes level once its radius is this fraction past a
+// threshold, so one hovering right at it doesn't flicker between meshes
+const LOD_HYSTERESIS: f32 = 0.15;
+const LOD_TRIANGLE_BUDGETS: [usize; 2] = [480, 200];
+// Icosphere subdivisions of each level of the default sphere (1280, 320 and
+// 80 triangles), and the radius to give them
+const SPHERE_SUBDIVISIONS: [usize; 3] = [3, 2, 1];
+const SPHERE_RADIUS: f32 = 0.5;
+// (rings, sectors) of each level of the sphere drawn in place of a model
+// that fails to load, about as many triangles as the default sphere's
+const GENERATED_SPHERE_LODS: [(usize, usize); 3] = [(20, 32), (10, 16), (5, 8)];
+
+// `current` is the level the body was drawn with last frame, if any. Each
+// threshold is moved LOD_HYSTERESIS away from the side the body is on.
+fn select_lod(world_position: Vec3, radius: f32, eye: Vec3, projection_matrix: &Mat4, viewport_height: f32, current: Option<usize>) -> usize {
+    // Perspective matrices have a 0 in the bottom-right corner; orthographic
+    // ones keep w = 1, so size doesn't shrink with distance
+    let distance = if projection_matrix[(3, 3)] == 0.0 {
+        (world_position - eye).magnitude().max(0.001)
+    } else {
+        1.0
+    };
+    let screen_radius = radius * projection_matrix[(1, 1)] / distance * viewport_height * 0.5;
+
+    LOD_SCREEN_RADII.iter()
+        .enumerate()
+        .position(|(level, &min_radius)| {
+            let band = match current {
+                Some(current) if current <= level => 1.0 - LOD_HYSTERESIS,
+                Some(_) => 1.0 + LOD_HYSTERESIS,
+                None => 1.0,
+            };
+            screen_radius >= min_radius * band
+        })
+        .unwrap_or(LOD_SCREEN_RADII.len())
+}
+
+// Circular orbit in the XY plane through the origin. `position` only
+// contributes its distance from the origin and its Z offset.
+fn orbit_position(position: &Vec3, speed: f32, phase: f32, seconds: f32) -> Vec3 {
+    let orbit_radius = position.magnitude();
+    let orbit_angle = seconds * TICKS_PER_SECOND * speed * 0.01 + phase;
+
+    Vec3::new(
+        orbit_radius * orbit_angle.cos(),
+        orbit_radius * orbit_angle.sin(),
+        position.z,
+    )
+}
+
+// World position of every body at `seconds`. Moons orbit their parent's
+// position from this same frame, so parents must come earlier in the list.
+fn body_positions(bodies: &[PlanetDesc], seconds: f32) -> Vec<Vec3> {
+    let mut positions: Vec<Vec3> = Vec::with_capacity(bodies.len());
+    for body in bodies {
+        let center = body.parent.map_or(Vec3::zeros(), |parent| positions[parent]);
+        positions.push(center + orbit_position(&body.position, body.orbit_speed, body.phase, seconds));
+    }
+    positions
+}
+
+fn create_model_matrix(translation: Vec3, scale: f32, rotation: Vec3) -> Mat4 {
+    let (sin_x, cos_x) = rotation.x.sin_cos();
+    let (sin_y, cos_y) = rotation.y.sin_cos();
+    let (sin_z, cos_z) = rotation.z.sin_cos();
+
+    let rotation_matrix_x = Mat4::new(
+        1.0,  0.0,    0.0,   0.0,
+        0.0,  cos_x, -sin_x, 0.0,
+        0.0,  sin_x,  cos_x, 0.0,
+        0.0,  0.0,    0.0,   1.0,
+    );
+
+    let rotation_matrix_y = Mat4::new(
+        cos_y,  0.0,  sin_y, 0.0,
+        0.0,    1.0,  0.0,   0.0,
+        -sin_y, 0.0,  cos_y, 0.0,
+        0.0,    0.0,  0.0,   1.0,
+    );
+
+    let rotation_matrix_z = Mat4::new(
+        cos_z, -sin_z, 0.0, 0.0,
+        sin_z,  cos_z, 0.0, 0.0,
+        0.0,    0.0,  1.0, 0.0,
+        0.0,    0.0,  0.0, 1.0,
+    );
+
+    let rotation_matrix = rotation_matrix_z * rotation_matrix_y * rotation_matrix_x;
+
+    let transform_matrix = Mat4::new(
+        scale, 0.0,   0.0,   translation.x,
+        0.0,   scale, 0.0,   translation.y,
+        0.0,   0.0,   scale, translation.z,
+        0.0,   0.0,   0.0,   1.0,
+    );
+
+    transform_matrix * rotation_matrix
+}
+
+#[allow(dead_code)]
+fn create_orbit_matrix(center: Vec3, radius: f32, speed: f32, time: f32) -> Mat4 {
+    let angle = time * speed;
+    let x = center.x + radius * angle.cos();
+    let y = center.y; 
+    let z = center.z + radius * angle.sin();
+    Mat4::new_translation(&Vec3::new(x, y, z))
+}
+
+fn create_view_matrix(eye: Vec3, center: Vec3, up: Vec3) -> Mat4 {
+    look_at(&eye, &center, &up)
+}
+
+// `fov` is the vertical field of view in degrees. nalgebra_glm takes the
+// aspect ratio first and the angle second.
+fn create_perspective_matrix(window_width: f32, window_height: f32, fov: f32, near: f32, far: f32) -> Mat4 {
+    let fov = fov * PI / 180.0;
+    let aspect_ratio = window_width / window_height;
+
+    perspective(aspect_ratio, fov, near, far)
+}
+
+// Orthographic counterpart of create_perspective_matrix. The view volume is
+// ORTHO_HALF_HEIGHT world units above and below the view axis, which fits
+// the whole system. Like the perspective matrix, near/far map to NDC z in
+// [-1, 1], so the depth buffer and clipping work unchanged (depth is just
+// linear instead of hyperbolic).
+fn create_orthographic_matrix(window_width: f32, window_height: f32, near: f32, far: f32) -> Mat4 {
+    let aspect_ratio = window_width / window_height;
+    let half_height = ORTHO_HALF_HEIGHT;
+    let half_width = half_height * aspect_ratio;
+
+    ortho(-half_width, half_width, -half_height, half_height, near, far)
+}
+
+fn create_viewport_matrix(width: f32, height: f32) -> Mat4 {
+    Mat4::new(
+        width / 2.0, 0.0, 0.0, width / 2.0,
+        0.0, -height / 2.0, 0.0, height / 2.0,
+        0.0, 0.0, 1.0, 0.0,
+        0.0, 0.0, 0.0, 1.0
+    )
+}
+
+pub fn render(framebuffer: &mut Framebuffer, uniforms: &Uniforms, vertex_array: &[Vertex], shader_index: usize, stats: &mut RenderStats) {
+    #[cfg(feature = "parallel")]
+    let transformed_vertices = if uniforms.parallel {
+        transform_vertices_parallel(vertex_array, uniforms)
+    } else {
+        transform_vertices(vertex_array, uniforms)
+    };
+    #[cfg(not(feature = "parallel"))]
+    let transformed_vertices = transform_vertices(vertex_array, uniforms);
+
+    let mut triangles = Vec::new();
+    for i in (0..transformed_vertices.len()).step_by(3) {
+        if i + 2 < transformed_vertices.len() {
+            let (v1, v2, v3) = (&transformed_vertices[i], &transformed_vertices[i + 1], &transformed_vertices[i + 2]);
+            let clipped = clip_triangle(v1, v2, v3);
+
+            if cfg!(feature = "stats") {
+                stats.triangles_submitted += 1;
+                if clipped.is_empty() {
+                    stats.triangles_culled += 1;
+                } else if !triangle_inside(v1, v2, v3) {
+                    stats.triangles_clipped += 1;
+                }
+            }
+
+            for mut tri in clipped {
+                for vertex in tri.iter_mut() {
+                    screen_mapping(vertex, uniforms);
+                }
+
+                let front_facing = is_front_facing(
+                    &tri[0].transformed_position,
+                    &tri[1].transformed_position,
+                    &tri[2].transformed_position,
+                );
+                if uniforms.cull_backfaces && !front_facing {
+                    if cfg!(feature = "stats") {
+                        stats.triangles_culled += 1;
+                    }
+                    continue;
+                }
+
+                if uniforms.flat_shading {
+                    if let Some(normal) = face_normal(&tri[0].world_position, &tri[1].world_position, &tri[2].world_position) {
+                        for vertex in tri.iter_mut() {
+                            vertex.transformed_normal = normal;
+                        }
+                    }
+                }
+
+                triangles.push(tri);
+            }
+        }
+    }
+
+    let mut fragments = Vec::new();
+    for tri in &triangles {
+        if uniforms.wireframe {
+            // Edge fragments go through the same shading and depth test as
+            // filled ones, so wires behind other geometry stay hidden
+            fragments.extend(line(&tri[0], &tri[1]));
+            fragments.extend(line(&tri[1], &tri[2]));
+            fragments.extend(line(&tri[2], &tri[0]));
+        } else {
+            triangle(&tri[0], &tri[1], &tri[2], &mut fragments);
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    let shaded = if uniforms.parallel {
+        shade_fragments_parallel(&fragments, uniforms, shader_index, framebuffer.width, framebuffer.height)
+    } else {
+        shade_fragments(&fragments, uniforms, shader_index, framebuffer.width, framebuffer.height)
+    };
+    #[cfg(not(feature = "parallel"))]
+    let shaded = shade_fragments(&fragments, uniforms, shader_index, framebuffer.width, framebuffer.height);
+
+    // Emissive bodies may be brighter than white; tone mapping at resolve
+    // time brings them back into range
+    let emission = if uniforms.material.emissive && !uniforms.debug_depth { uniforms.material.emission } else { 1.0 };
+
+    // Depth testing stays serial and in submission order, so the result
+    // matches the single-threaded path exactly
+    for (x, y, depth, color, alpha) in shaded {
+        let written = if alpha < 1.0 {
+            framebuffer.blend_point(x, y, depth, color, alpha)
+        } else {
+            framebuffer.set_current_radiance(color.to_rgb() * emission);
+            framebuffer.point(x, y, depth)
+        };
+
+        if cfg!(feature = "stats") {
+            stats.fragments_shaded += 1;
+            if !written {
+                stats.fragments_depth_failed += 1;
+            }
+        }
+    }
+
+    // Unshaded edges over the finished surface. The bias only has to beat
+    // the fill's own depth; anything actually in front still hides them.
+    if uniforms.wireframe_overlay && !uniforms.wireframe {
+        framebuffer.set_current_color(WIREFRAME_OVERLAY_COLOR);
+        for tri in &triangles {
+            for (a, b) in [(&tri[0], &tri[1]), (&tri[1], &tri[2]), (&tri[2], &tri[0])] {
+                for fragment in line(a, b) {
+                    if fragment.position.x >= 0.0 && fragment.position.y >= 0.0 {
+                        framebuffer.point(fragment.position.x as usize, fragment.position.y as usize, fragment.depth - WIREFRAME_DEPTH_BIAS);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn transform_vertices(vertices: &[Vertex], uniforms: &Uniforms) -> Vec<Vertex> {
+    vertices.iter().map(|vertex| vertex_shader(vertex, uniforms)).collect()
+}
+
+// The vertex shader only reads the uniforms, so vertices can be shaded on
+// any thread; the indexed collect keeps them in their original order
+#[cfg(feature = "parallel")]
+fn transform_vertices_parallel(vertices: &[Vertex], uniforms: &Uniforms) -> Vec<Vertex> {
+    vertices.par_iter().map(|vertex| vertex_shader(vertex, uniforms)).collect()
+}
+
+// Runs the fragment shader without touching the framebuffer, returning
+// (x, y, depth, color, alpha) for a fragment inside the given bounds
+fn shade_fragment(fragment: &Fragment, uniforms: &Uniforms, shader_index: usize, width: usize, height: usize) -> Option<(usize, usize, f32, Color, f32)> {
+    let x = fragment.position.x as usize;
+    let y = fragment.position.y as usize;
+
+    if x < width && y < height {
+        let color = fragment_shader(fragment, uniforms, shader_index);
+        let alpha = fragment_alpha(fragment, uniforms, shader_index);
+        Some((x, y, fragment.depth, color, alpha))
+    } else {
+        None
+    }
+}
+
+fn shade_fragments(fragments: &[Fragment], uniforms: &Uniforms, shader_index: usize, width: usize, height: usize) -> Vec<(usize, usize, f32, Color, f32)> {
+    fragments.iter()
+        .filter_map(|fragment| shade_fragment(fragment, uniforms, shader_index, width, height))
+        .collect()
+}
+
+// Rayon's collect keeps the fragments in submission order, so the serial
+// depth pass after it sees exactly what shade_fragments would give
+#[cfg(feature = "parallel")]
+fn shade_fragments_parallel(fragments: &[Fragment], uniforms: &Uniforms, shader_index: usize, width: usize, height: usize) -> Vec<(usize, usize, f32, Color, f32)> {
+    fragments.par_iter()
+        .with_min_len(MIN_PARALLEL_CHUNK)
+        .filter_map(|fragment| shade_fragment(fragment, uniforms, shader_index, width, height))
+        .collect()
+}
+
+// Direction towards the light from yaw (around Y) and pitch (above the XZ plane)
+fn light_direction(yaw: f32, pitch: f32) -> Vec3 {
+    Vec3::new(
+        pitch.cos() * yaw.sin(),
+        pitch.sin(),
+        pitch.cos() * yaw.cos(),
+    ).normalize()
+}
+
+// Distance fog for the grid and axes, the same blend the bodies get from
+// the fragment shader. A density of 0 leaves the lines alone.
+struct LineFog<'a> {
+    color: Vec3,
+    density: f32,
+    near: f32,
+    far: f32,
+    projection_matrix: &'a Mat4,
+}
+
+impl LineFog<'_> {
+    fn apply(&self, color: Color, depth: f32) -> Color {
+        fog_blend(color, &self.color, self.density, view_distance(depth, self.near, self.far, self.projection_matrix))
+    }
+}
+
+fn draw_debug_line(framebuffer: &mut Framebuffer, start: Vec3, end: Vec3, color: Color, view_projection: &Mat4, viewport_matrix: &Mat4, fog: Option<&LineFog>) {
+    let clip_start = view_projection * Vec4::new(start.x, start.y, start.z, 1.0);
+    let clip_end = view_projection * Vec4::new(end.x, end.y, end.z, 1.0);
+    let Some((clip_start, clip_end)) = clip_line(clip_start, clip_end) else {
+        return;
+    };
+
+    let mut endpoints = [Vertex::new_with_color(start, color), Vertex::new_with_color(end, color)];
+    for (vertex, clip) in endpoints.iter_mut().zip([clip_start, clip_end]) {
+        let screen = viewport_matrix * Vec4::new(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w, 1.0);
+        vertex.transformed_position = Vec3::new(screen.x, screen.y, screen.z);
+    }
+
+    for fragment in line(&endpoints[0], &endpoints[1]) {
+        if fragment.position.x < 0.0 || fragment.position.y < 0.0 {
+            continue;
+        }
+        let color = fog.map_or(fragment.color, |fog| fog.apply(fragment.color, fragment.depth));
+        framebuffer.set_current_color(color.to_hex());
+        framebuffer.point(fragment.position.x as usize, fragment.position.y as usize, fragment.depth);
+    }
+}
+
+// Line elements of a model, moved into the world by `model_matrix` and drawn
+// in their own color. They go through the depth test but aren't shaded.
+fn render_lines(framebuffer: &mut Framebuffer, lines: &[[Vertex; 2]], model_matrix: &Mat4, view_projection: &Mat4, viewport_matrix: &Mat4) {
+    let to_world = |vertex: &Vertex| (model_matrix * Vec4::new(vertex.position.x, vertex.position.y, vertex.position.z, 1.0)).xyz();
+    for [start, end] in lines {
+        draw_debug_line(framebuffer, to_world(start), to_world(end), start.color, view_projection, viewport_matrix, None);
+    }
+}
+
+// X (red), Y (green) and Z (blue) axes from the origin. They're drawn before
+// the bodies, so anything in front covers them through the depth test.
+fn draw_axes(framebuffer: &mut Framebuffer, view_matrix: &Mat4, projection_matrix: &Mat4, viewport_matrix: &Mat4, fog: &LineFog) {
+    let view_projection = projection_matrix * view_matrix;
+    let axes = [
+        (Vec3::x(), Color::new(255, 60, 60)),
+        (Vec3::y(), Color::new(60, 255, 60)),
+        (Vec3::z(), Color::new(60, 60, 255)),
+    ];
+    for (axis, color) in axes {
+        draw_debug_line(framebuffer, Vec3::zeros(), axis * AXES_LENGTH, color, &view_projection, viewport_matrix, Some(fog));
+    }
+}
+
+// Reference grid on the XZ plane, with the X and Z axes drawn brighter
+fn draw_grid(framebuffer: &mut Framebuffer, view_projection: &Mat4, viewport_matrix: &Mat4, fog: &LineFog) {
+    let grid_color = Color::new(60, 60, 90);
+    let axis_color = Color::new(140, 140, 190);
+    let extent = GRID_HALF_LINES as f32 * GRID_SPACING;
+
+    for i in -GRID_HALF_LINES..=GRID_HALF_LINES {
+        let offset = i as f32 * GRID_SPACING;
+        let color = if i == 0 { axis_color } else { grid_color };
+
+        draw_debug_line(
+            framebuffer,
+            Vec3::new(-extent, 0.0, offset),
+            Vec3::new(extent, 0.0, offset),
+            color,
+            view_projection,
+            viewport_matrix,
+            Some(fog),
+        );
+        draw_debug_line(
+            framebuffer,
+            Vec3::new(offset, 0.0, -extent),
+            Vec3::new(offset, 0.0, extent),
+            color,
+            view_projection,
+            viewport_matrix,
+            Some(fog),
+        );
+    }
+}
+
+// Each body's orbit as a dim circle around its parent (or the origin). The
+// points come from orbit_position itself, swept through a full turn, so the
+// circle is exactly the path the body follows. Bodies sitting at their
+// center (the sun) have no orbit to draw.
+fn draw_orbit_paths(framebuffer: &mut Framebuffer, bodies: &[PlanetDesc], positions: &[Vec3], view_projection: &Mat4, viewport_matrix: &Mat4) {
+    let path_color = Color::new(70, 70, 70);
+
+    for body in bodies {
+        if body.position.magnitude() <= f32::EPSILON {
+            continue;
+        }
+        let center = body.parent.map_or(Vec3::zeros(), |parent| positions[parent]);
+        let point = |segment: usize| {
+            let angle = segment as f32 / ORBIT_PATH_SEGMENTS as f32 * 2.0 * PI;
+            center + orbit_position(&body.position, 0.0, angle, 0.0)
+        };
+        for segment in 0..ORBIT_PATH_SEGMENTS {
+            draw_debug_line(framebuffer, point(segment), point(segment + 1), path_color, view_projection, viewport_matrix, None);
+        }
+    }
+}
+
+// Command-line settings, filled in by parse_options
+pub struct Options {
+    timescale: f32,
+    width: usize,
+    height: usize,
+    gamma: f32,
+    near: f32,
+    far: f32,
+    fog_density: Option<f32>,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    tone_mapping: ToneMapping,
+    normalize_model: bool,
+    // JSON file replacing the built-in bodies
+    scene: Option<String>,
+    // Render without a window, save to `out` and exit
+    headless: bool,
+    out: String,
+    frames: usize,
+    // Also save every Nth frame, numbered, when set
+    every: Option<usize>,
+    // Headless: take camera and time from this recording, one frame each
+    playback: Option<String>,
+}
+
+// The settings a run without any arguments gets
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            timescale: 1.0,
+            width: 800,
+            height: 600,
+            gamma: 2.2,
+            near: 0.1,
+            far: 1000.0,
+            fog_density: None,
+            bloom_threshold: BLOOM_THRESHOLD,
+            bloom_intensity: BLOOM_INTENSITY,
+            tone_mapping: ToneMapping::Aces,
+            normalize_model: false,
+            scene: None,
+            headless: false,
+            out: String::from("frame.png"),
+            frames: 1,
+            every: None,
+            playback: None,
+        }
+    }
+}
+
+fn parse_options() -> Options {
+    let mut options = Options::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--timescale" => match args.next().map(|value| value.parse::<f32>()) {
+                Some(Ok(value)) if value.is_finite() && value >= 0.0 => options.timescale = value,
+                _ => eprintln!("--timescale expects a non-negative number, using {}", options.timescale),
+            },
+            "--width" => match args.next().map(|value| value.parse::<usize>()) {
+                Some(Ok(value)) if value > 0 => options.width = value,
+                _ => eprintln!("--width expects a positive integer, using {}", options.width),
+            },
+            "--height" => match args.next().map(|value| value.parse::<usize>()) {
+                Some(Ok(value)) if value > 0 => options.height = value,
+                _ => eprintln!("--height expects a positive integer, using {}", options.height),
+            },
+            "--gamma" => match args.next().map(|value| value.parse::<f32>()) {
+                Some(Ok(value)) if value.is_finite() && value > 0.0 => options.gamma = value,
+                _ => eprintln!("--gamma expects a positive number, using {}", options.gamma),
+            },
+            "--near" => match args.next().map(|value| value.parse::<f32>()) {
+                Some(Ok(value)) if value.is_finite() && value > 0.0 => options.near = value,
+                _ => eprintln!("--near expects a positive number, using {}", options.near),
+            },
+            "--far" => match args.next().map(|value| value.parse::<f32>()) {
+                Some(Ok(value)) if value.is_finite() && value > 0.0 => options.far = value,
+                _ => eprintln!("--far expects a positive number, using {}", options.far),
+            },
+            "--fog" => match args.next().map(|value| value.parse::<f32>()) {
+                Some(Ok(value)) if value.is_finite() && value > 0.0 => options.fog_density = Some(value),
+                _ => eprintln!("--fog expects a positive density, leaving fog off"),
+            },
+            "--bloom-threshold" => match args.next().map(|value| value.parse::<f32>()) {
+                Some(Ok(value)) if value.is_finite() && value >= 0.0 => options.bloom_threshold = value,
+                _ => eprintln!("--bloom-threshold expects a non-negative number, using {}", options.bloom_threshold),
+            },
+            "--bloom-intensity" => match args.next().map(|value| value.parse::<f32>()) {
+                Some(Ok(value)) if value.is_finite() && value >= 0.0 => options.bloom_intensity = value,
+                _ => eprintln!("--bloom-intensity expects a non-negative number, using {}", options.bloom_intensity),
+            },
+            "--tonemap" => match args.next().as_deref().and_then(ToneMapping::from_name) {
+                Some(tone_mapping) => options.tone_mapping = tone_mapping,
+                None => eprintln!("--tonemap expects none, reinhard or aces, using {}", options.tone_mapping.name()),
+            },
+            "--normalize" => options.normalize_model = true,
+            "--scene" => match args.next() {
+                Some(path) => options.scene = Some(path),
+                None => eprintln!("--scene expects a file path, using the built-in bodies"),
+            },
+            "--headless" => options.headless = true,
+            "--out" => match args.next() {
+                Some(path) => options.out = path,
+                None => eprintln!("--out expects a file path, using {}", options.out),
+            },
+            "--frames" => match args.next().map(|value| value.parse::<usize>()) {
+                Some(Ok(value)) if value > 0 => options.frames = value,
+                _ => eprintln!("--frames expects a positive integer, using {}", options.frames),
+            },
+            "--playback" => match args.next() {
+                Some(path) => options.playback = Some(path),
+                None => eprintln!("--playback expects a recording file, ignoring it"),
+            },
+            "--every" => match args.next().map(|value| value.parse::<usize>()) {
+                Some(Ok(value)) if value > 0 => options.every = Some(value),
+                _ => eprintln!("--every expects a positive integer, ignoring it"),
+            },
+            _ => eprintln!("Ignoring unknown argument: {}", arg),
+        }
+    }
+    if options.near >= options.far {
+        eprintln!("--near ({}) must be less than --far ({}), using 0.1 and 1000", options.near, options.far);
+        options.near = 0.1;
+        options.far = 1000.0;
+    }
+    options
+}
+
+// A mesh at every level of detail, full resolution first, plus any line
+// elements. `radius` bounds the surface around the origin and is what
+// shadows, LOD and camera collisions use; `bounds` also covers the lines and
+// decides frustum culling.
+struct Model {
+    lods: Vec<Vec<Vertex>>,
+    lines: Vec<[Vertex; 2]>,
+    radius: f32,
+    bounds: f32,
+}
+
+impl Model {
+    fn new(lods: Vec<Vec<Vertex>>, lines: Vec<[Vertex; 2]>) -> Self {
+        let farthest = |vertices: &mut dyn Iterator<Item = &Vertex>| vertices
+            .map(|vertex| vertex.position.magnitude())
+            .fold(0.0, f32::max);
+        let radius = farthest(&mut lods[0].iter());
+        let bounds = radius.max(farthest(&mut lines.iter().flatten()));
+        Model { lods, lines, radius, bounds }
+    }
+}
+
+fn load_model(path: &str, options: &Options) -> Result<Model, tobj::LoadError> {
+    let mut obj = Obj::load(path)?;
+    if options.normalize_model {
+        obj.normalize();
+    }
+    let mut lods = vec![obj.get_vertex_array()];
+    for budget in LOD_TRIANGLE_BUDGETS {
+        lods.push(obj.decimate(budget).get_vertex_array());
+    }
+    Ok(Model::new(lods, obj.get_line_array()))
+}
+
+// Noise generators for one body, built from its seed
+struct BodyNoise {
+    cloud: FastNoiseLite,
+    fractal: FastNoiseLite,
+}
+
+// Everything loaded once at startup and shared by every frame
+pub struct Scene {
+    bodies: Vec<PlanetDesc>,
+    noises: Vec<BodyNoise>,
+    environment: Environment,
+    texture: Option<Texture>,
+    // The default sphere first, then any other model a scene file names
+    models: Vec<Model>,
+    // Index into `models` for each body
+    body_models: Vec<usize>,
+    ring_vertices: Vec<Vertex>,
+    // The only thing a frame changes: the level of detail each body was
+    // last drawn with, for select_lod's hysteresis. None until first drawn.
+    lod_levels: Vec<Cell<Option<usize>>>,
+}
+
+// Lights and view toggles that the keyboard changes between frames
+pub struct RenderSettings {
+    light_dir: Vec3,
+    sun_lighting: bool,
+    light_pos: Vec3,
+    light_color: Vec3,
+    show_grid: bool,
+    show_orbits: bool,
+    cull_backfaces: bool,
+    wireframe: bool,
+    wireframe_overlay: bool,
+    noise_params: NoiseParams,
+    flat_shading: bool,
+    orthographic: bool,
+    debug_depth: bool,
+    fov: f32,
+    near: f32,
+    far: f32,
+    fog: bool,
+    fog_density: f32,
+    bloom: bool,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    parallel: bool,
+}
+
+impl RenderSettings {
+    fn new() -> Self {
+        RenderSettings {
+            light_dir: light_direction(0.0, 0.0),
+            sun_lighting: true,
+            light_pos: Vec3::new(0.0, 1.5, 2.5),
+            light_color: Vec3::new(1.0, 0.95, 0.85) * POINT_LIGHT_STRENGTH,
+            show_grid: false,
+            show_orbits: false,
+            cull_backfaces: true,
+            wireframe: false,
+            wireframe_overlay: false,
+            noise_params: NoiseParams::default(),
+            flat_shading: false,
+            orthographic: false,
+            debug_depth: false,
+            fov: DEFAULT_FOV,
+            near: 0.1,
+            far: 1000.0,
+            fog: false,
+            fog_density: FOG_DENSITY,
+            bloom: true,
+            bloom_threshold: BLOOM_THRESHOLD,
+            bloom_intensity: BLOOM_INTENSITY,
+            parallel: true,
+        }
+    }
+}
+
+// Starting settings with the command-line overrides applied
+pub fn initial_settings(options: &Options) -> RenderSettings {
+    RenderSettings {
+        near: options.near,
+        far: options.far,
+        fog: options.fog_density.is_some(),
+        fog_density: options.fog_density.unwrap_or(FOG_DENSITY),
+        bloom_threshold: options.bloom_threshold,
+        bloom_intensity: options.bloom_intensity,
+        ..RenderSettings::new()
+    }
+}
+
+// The sphere every body without its own model is drawn with
+fn sphere_lods() -> Vec<Vec<Vertex>> {
+    SPHERE_SUBDIVISIONS.iter()
+        .map(|&subdivisions| {
+            let mut vertices = primitives::icosphere(subdivisions);
+            for vertex in vertices.iter_mut() {
+                vertex.position *= SPHERE_RADIUS;
+            }
+            vertices
+        })
+        .collect()
+}
+
+// Stand-in for a model that fails to load, at the default sphere's radius
+fn generated_sphere_lods() -> Vec<Vec<Vertex>> {
+    GENERATED_SPHERE_LODS.iter()
+        .map(|&(rings, sectors)| {
+            let mut vertices = primitives::uv_sphere(rings, sectors);
+            for vertex in vertices.iter_mut() {
+                vertex.position *= SPHERE_RADIUS;
+            }
+            vertices
+        })
+        .collect()
+}
+
+// Bodies used when no scene file is given. The first one is the sun.
+fn built_in_bodies() -> Vec<PlanetDesc> {
+    let night_tint = Color::new(70, 80, 110);
+    let ocean_color = Color::new(0, 105, 148);
+    let white = Color::new(255, 255, 255);
+    vec![
+        PlanetDesc {
+            material: Material::emissive(Color::new(255, 140, 0)).with_emission(2.5), spin: 0.2, seed: 1337,
+            ..PlanetDesc::new(Vec3::new(0.0, 0.0, 0.0), 0.7, 0)
+        },
+        PlanetDesc {
+            orbit_speed: 0.2, spin: 0.4, seed: 2024,
+            ..PlanetDesc::new(Vec3::new(-2.0, 0.0, 0.0), 0.5, 1)
+        },
+        PlanetDesc {
+            orbit_speed: 0.2, phase: 1.0, material: Material::matte(Color::new(30, 97, 145)), spin: 0.3, seed: 31,
+            ..PlanetDesc::new(Vec3::new(2.0, 0.0, 0.0), 0.5, 2)
+        },
+        PlanetDesc {
+            orbit_speed: 0.2, phase: 2.0, material: Material::matte(Color::new(124, 252, 0)), spin: 0.4, seed: 977,
+            ..PlanetDesc::new(Vec3::new(0.0, 2.0, 0.0), 0.5, 3)
+        },
+        PlanetDesc {
+            orbit_speed: 0.2, phase: 3.0, material: Material::emissive(Color::new(255, 240, 0)).with_emission(1.6), spin: 0.3, seed: 4242,
+            ..PlanetDesc::new(Vec3::new(0.0, -2.0, 0.0), 0.5, 4)
+        },
+        PlanetDesc {
+            orbit_speed: 0.2, phase: 4.0, material: Material::new(Color::new(205, 133, 63), white, 16.0, 0.8, false), spin: 0.25, seed: 613,
+            ..PlanetDesc::new(Vec3::new(1.5, 1.5, 0.0), 0.5, 5)
+        },
+        PlanetDesc {
+            orbit_speed: 0.2, phase: 5.0, material: Material::new(ocean_color, white, 64.0, 0.3, false), ambient: night_tint, spin: 0.5, seed: 1337,
+            ..PlanetDesc::new(Vec3::new(-1.5, -1.5, 0.0), 0.5, 6)
+        },
+        PlanetDesc {
+            orbit_speed: 0.1, phase: 0.5, material: Material::matte(Color::new(135, 206, 250)), spin: 0.8, seed: 8101,
+            ..PlanetDesc::new(Vec3::new(3.0, 0.0, 0.0), 0.6, 7)
+        },
+        PlanetDesc {
+            orbit_speed: 0.15, phase: 2.5, material: Material::new(Color::new(90, 90, 100), white, 128.0, 0.1, false), spin: 0.2, seed: 59,
+            ..PlanetDesc::new(Vec3::new(0.0, 3.5, 0.0), 0.4, 8)
+        },
+        PlanetDesc {
+            orbit_speed: 0.12, phase: 4.0, material: Material::new(ocean_color, white, 8.0, 0.3, false), ambient: night_tint, spin: 0.5, seed: 7331,
+            ..PlanetDesc::new(Vec3::new(0.0, -3.5, 0.0), 0.45, 6)
+        },
+        PlanetDesc {
+            orbit_speed: 0.18, phase: 5.5, material: Material::new(Color::new(160, 160, 165), white, 8.0, 0.9, false), spin: 0.1, seed: 271,
+            ..PlanetDesc::new(Vec3::new(2.6, 0.0, 0.0), 0.3, 11)
+        },
+        PlanetDesc {
+            orbit_speed: 0.08, phase: 3.5, material: Material::new(Color::new(200, 225, 240), white, 48.0, 0.4, false), ambient: Color::new(60, 80, 110), spin: 0.3, seed: 1618,
+            ..PlanetDesc::new(Vec3::new(4.2, 0.0, 0.0), 0.4, 10)
+        },
+        // Tilted out of the orbital plane to stay clear of the ice planet
+        PlanetDesc {
+            orbit_speed: 0.09, phase: 4.9, material: Material::new(Color::new(8, 40, 110), white, 96.0, 0.2, false), ambient: night_tint, spin: 0.3, seed: 4077,
+            ..PlanetDesc::new(Vec3::new(3.8, 0.0, 0.8), 0.35, 12)
+        },
+        // Moon of the first earth-like planet
+        PlanetDesc {
+            orbit_speed: 1.2, material: Material::new(Color::new(170, 170, 175), white, 8.0, 0.9, false), spin: 0.1, seed: 907, parent: Some(6),
+            ..PlanetDesc::new(Vec3::new(0.75, 0.0, 0.1), 0.12, 11)
+        },
+    ]
+}
+
+pub fn load_scene(options: &Options) -> Scene {
+    let loaded = options.scene.as_deref().and_then(|path| match scene::load(path) {
+        Ok(planets) if !planets.is_empty() => Some(planets),
+        Ok(_) => {
+            eprintln!("{} has no bodies, using the built-in ones", path);
+            None
+        }
+        Err(err) => {
+            eprintln!("Failed to load scene ({}), using the built-in bodies", err);
+            None
+        }
+    });
+    let bodies = loaded.unwrap_or_else(built_in_bodies);
+
+    for (index, body) in bodies.iter().enumerate() {
+        if let Some(parent) = body.parent {
+            assert!(parent < index, "body {} orbits body {}, which must come before it", index, parent);
+        }
+    }
+
+    let noises = bodies.iter()
+        .map(|body| BodyNoise {
+            cloud: create_cloud_noise_with_seed(body.seed),
+            fractal: create_fractal_noise(body.seed, 4, 2.0, 0.5),
+        })
+        .collect();
+
+    let environment = Environment::starfield(256, 128, 42);
+    let texture = match Texture::load_ppm("assets/textures/moon.ppm") {
+        Ok(texture) => Some(texture),
+        Err(err) => {
+            eprintln!("Failed to load texture: {}", err);
+            None
+        }
+    };
+
+    let mut models = vec![Model::new(sphere_lods(), Vec::new())];
+
+    // Each distinct path is loaded once; bodies whose model fails to load
+    // are drawn with a generated UV sphere, built the first time one fails
+    let mut loaded_models: Vec<(&str, usize)> = Vec::new();
+    let mut fallback_model = None;
+    let body_models = bodies.iter()
+        .map(|body| {
+            let Some(path) = body.model.as_deref() else {
+                return 0;
+            };
+            if let Some(&(_, index)) = loaded_models.iter().find(|&&(loaded, _)| loaded == path) {
+                return index;
+            }
+            let index = match load_model(path, options) {
+                Ok(model) => {
+                    models.push(model);
+                    models.len() - 1
+                }
+                Err(err) => {
+                    eprintln!("Failed to load {} ({}), using a generated sphere", path, err);
+                    *fallback_model.get_or_insert_with(|| {
+                        models.push(Model::new(generated_sphere_lods(), Vec::new()));
+                        models.len() - 1
+                    })
+                }
+            };
+            loaded_models.push((path, index));
+            index
+        })
+        .collect();
+
+    let ring_vertices = primitives::ring(RING_INNER_RADIUS, RING_OUTER_RADIUS, 96);
+
+    let lod_levels = vec![Cell::new(None); bodies.len()];
+
+    Scene { bodies, noises, environment, texture, models, body_models, ring_vertices, lod_levels }
+}
+
+pub fn create_camera() -> Camera {
+    let mut camera = Camera::new(
+        Vec3::new(0.0, 3.0, 5.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+    );
+    camera.set_zoom_limits(CAMERA_MIN_DISTANCE, CAMERA_MAX_DISTANCE);
+    camera
+}
+
+// Where each body is this frame and how big it is, as bounding spheres
+fn body_spheres(scene: &Scene, positions: &[Vec3]) -> Vec<(Vec3, f32)> {
+    positions.iter()
+        .zip(&scene.bodies)
+        .zip(&scene.body_models)
+        .map(|((&position, body), &model)| (position, scene.models[model].radius * body.scale))
+        .collect()
+}
+
+// Draws one complete frame of the scene at `seconds` into the supersampled
+// framebuffer, with bodies placed along their orbits at `orbit_seconds`.
+// Shared by the window loop and headless mode.
+pub fn render_frame(framebuffer: &mut Framebuffer, scene: &Scene, settings: &RenderSettings, seconds: f32, orbit_seconds: f32, camera: &Camera) -> RenderStats {
+    let time = (seconds * TICKS_PER_SECOND) as u32;
+    let (width, height) = (framebuffer.width as f32, framebuffer.height as f32);
+
+    framebuffer.clear();
+    framebuffer.draw_starfield(STAR_SEED, STAR_DENSITY, SUPERSAMPLE);
+    let mut stats = RenderStats::default();
+
+    let positions = body_positions(&scene.bodies, orbit_seconds);
+    let shadow_casters = body_spheres(scene, &positions);
+    // The first body is the sun and lights everything else
+    let sun_pos = positions[0];
+
+    let view_matrix = create_view_matrix(camera.eye, camera.center, camera.up);
+    let projection_matrix = if settings.orthographic {
+        create_orthographic_matrix(width, height, settings.near, settings.far)
+    } else {
+        create_perspective_matrix(width, height, settings.fov, settings.near, settings.far)
+    };
+    let viewport_matrix = create_viewport_matrix(width, height);
+    let view_projection = projection_matrix * view_matrix;
+
+    let fog_color = Color::from_hex(BACKGROUND_TOP).lerp(&Color::from_hex(BACKGROUND_BOTTOM), 0.5).to_rgb();
+    let fog_density = if settings.fog { settings.fog_density } else { 0.0 };
+
+    if settings.show_grid {
+        let fog = LineFog { color: fog_color, density: fog_density, near: settings.near, far: settings.far, projection_matrix: &projection_matrix };
+        // Axes first: where they overlap the grid's center lines at equal
+        // depth, the first line drawn wins
+        draw_axes(framebuffer, &view_matrix, &projection_matrix, &viewport_matrix, &fog);
+        draw_grid(framebuffer, &view_projection, &viewport_matrix, &fog);
+    }
+    if settings.show_orbits {
+        draw_orbit_paths(framebuffer, &scene.bodies, &positions, &view_projection, &viewport_matrix);
+    }
+
+    let mut ring_draws = Vec::new();
+    let mut atmosphere_draws = Vec::new();
+    for (index, ((body, noise), &orbit_position)) in scene.bodies.iter().zip(&scene.noises).zip(&positions).enumerate() {
+        let spin_angle = seconds * TICKS_PER_SECOND * body.spin * 0.01;
+
+        // Skip whole draws before any vertex work when their bounding sphere
+        // is off screen. The ring and the atmosphere reach further out than
+        // their planet.
+        let model = &scene.models[scene.body_models[index]];
+        let body_visible = sphere_in_frustum(orbit_position, model.bounds * body.scale, &view_projection);
+        let ring_visible = body.shader == RINGED_SHADER_INDEX
+            && sphere_in_frustum(orbit_position, RING_OUTER_RADIUS * body.scale, &view_projection);
+        let sphere = &scene.models[0];
+        let atmosphere_visible = body.shader == ATMOSPHERIC_SHADER_INDEX
+            && sphere_in_frustum(orbit_position, sphere.radius * ATMOSPHERE_SCALE * body.scale, &view_projection);
+        if cfg!(feature = "stats") {
+            stats.objects_culled += usize::from(!body_visible)
+                + usize::from(body.shader == RINGED_SHADER_INDEX && !ring_visible)
+                + usize::from(body.shader == ATMOSPHERIC_SHADER_INDEX && !atmosphere_visible);
+        }
+        if !body_visible && !ring_visible && !atmosphere_visible {
+            continue;
+        }
+
+        let model_matrix = create_model_matrix(orbit_position, body.scale, Vec3::new(0.0, spin_angle, 0.0));
+
+        let uniforms = Uniforms {
+            model_matrix,
+            view_matrix,
+            projection_matrix,
+            viewport_matrix,
+            time,
+            seconds,
+            noise: &noise.cloud,
+            fractal_noise: &noise.fractal,
+            material: body.material,
+            ambient_color: body.ambient,
+            ambient: AMBIENT_LIGHT,
+            light_dir: settings.light_dir,
+            sun_pos,
+            sun_lighting: settings.sun_lighting,
+            light_pos: settings.light_pos,
+            light_color: settings.light_color,
+            dither_strength: DITHER_STRENGTH,
+            camera_position: camera.eye,
+            environment: &scene.environment,
+            texture: scene.texture.as_ref(),
+            shadow_casters: &shadow_casters,
+            body_index: index,
+            cull_backfaces: settings.cull_backfaces,
+            wireframe: settings.wireframe,
+            wireframe_overlay: settings.wireframe_overlay,
+            noise_params: settings.noise_params,
+            flat_shading: settings.flat_shading,
+            debug_depth: settings.debug_depth,
+            near: settings.near,
+            far: settings.far,
+            fog_color,
+            fog_density,
+            parallel: settings.parallel,
+        };
+
+        // LOD thresholds are in window pixels, not supersampled ones
+        let lod = select_lod(
+            orbit_position,
+            model.radius * body.scale,
+            camera.eye,
+            &projection_matrix,
+            height / SUPERSAMPLE as f32,
+            scene.lod_levels[index].get(),
+        );
+        scene.lod_levels[index].set(Some(lod));
+
+        if body_visible {
+            framebuffer.set_current_id(Some(index));
+            render(framebuffer, &uniforms, &model.lods[lod], body.shader, &mut stats);
+            render_lines(framebuffer, &model.lines, &model_matrix, &view_projection, &viewport_matrix);
+        }
+
+        if ring_visible {
+            ring_draws.push(Uniforms {
+                model_matrix: create_model_matrix(orbit_position, body.scale, RING_TILT),
+                material: Material::matte(Color::new(200, 180, 140)),
+                // Culled regardless of the setting: the ring has both faces, and
+                // drawing both would blend every pixel twice
+                cull_backfaces: true,
+                ..uniforms
+            });
+        }
+
+        // Always the default sphere, at the body's level of detail
+        if atmosphere_visible {
+            let vertices = &sphere.lods[lod.min(sphere.lods.len() - 1)];
+            atmosphere_draws.push((Uniforms {
+                model_matrix: create_model_matrix(orbit_position, body.scale * ATMOSPHERE_SCALE, Vec3::new(0.0, spin_angle, 0.0)),
+                material: Material::matte(Color::new(255, 255, 255)),
+                ..uniforms
+            }, vertices));
+        }
+    }
+
+    framebuffer.set_current_id(None);
+
+    // Rings and atmospheres are translucent and don't write depth, so they go
+    // after every opaque body to blend over whatever ends up behind them
+    for (atmosphere_uniforms, vertices) in &atmosphere_draws {
+        render(framebuffer, atmosphere_uniforms, vertices, ATMOSPHERE_SHADER_INDEX, &mut stats);
+    }
+    for ring_uniforms in &ring_draws {
+        render(framebuffer, ring_uniforms, &scene.ring_vertices, RING_SHADER_INDEX, &mut stats);
+    }
+
+    // The I/J/K/L direction only matters with sun lighting off
+    if !settings.sun_lighting {
+        draw_debug_line(
+            framebuffer,
+            Vec3::zeros(),
+            settings.light_dir * 1.5,
+            Color::new(255, 255, 0),
+            &view_projection,
+            &viewport_matrix,
+            None,
+        );
+    }
+
+    // Small cross marking the point light
+    for axis in [Vec3::x(), Vec3::y(), Vec3::z()] {
+        draw_debug_line(
+            framebuffer,
+            settings.light_pos - axis * 0.1,
+            settings.light_pos + axis * 0.1,
+            Color::new(255, 255, 255),
+            &view_projection,
+            &viewport_matrix,
+            None,
+        );
+    }
+
+    stats
+}
+
+// The shader after (or before) `shader` for a body, wrapping around and
+// skipping the ring and atmosphere shaders, which only draw see-through
+// shells around one
+fn cycle_body_shader(shader: usize, forward: bool) -> usize {
+    let step = if forward { 1 } else { SHADER_COUNT - 1 };
+    let mut next = shader;
+    loop {
+        next = (next + step) % SHADER_COUNT;
+        if next != RING_SHADER_INDEX && next != ATMOSPHERE_SHADER_INDEX {
+            return next;
+        }
+    }
+}
+
+// "frame.png" -> "frame_0060.png"
+fn numbered_path(path: &str, frame: usize) -> String {
+    let path = std::path::Path::new(path);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("frame");
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("png");
+    path.with_file_name(format!("{}_{:04}.{}", stem, frame, extension))
+        .to_string_lossy()
+        .into_owned()
+}
+
+// Steps the clock by a fixed 1/TICKS_PER_SECOND (scaled by --timescale) per
+// frame, so the output doesn't depend on how fast the machine renders
+fn run_headless(options: &Options, scene: &Scene) {
+    let mut framebuffer = Framebuffer::new(options.width * SUPERSAMPLE, options.height * SUPERSAMPLE);
+    let mut display = Framebuffer::new(options.width, options.height);
+    framebuffer.set_background_gradient(BACKGROUND_TOP, BACKGROUND_BOTTOM);
+    display.set_gamma(options.gamma);
+    display.set_tone_mapping(options.tone_mapping);
+
+    let mut camera = create_camera();
+    let settings = initial_settings(options);
+    let step = options.timescale / TICKS_PER_SECOND;
+
+    let recording = options.playback.as_deref().map(|path| match Recording::load(path) {
+        Ok(recording) => recording,
+        Err(err) => {
+            eprintln!("Failed to load recording: {}", err);
+            std::process::exit(1);
+        }
+    });
+    let frames = recording.as_ref().map_or(options.frames, |recording| recording.frames.len());
+
+    for frame in 1..=frames {
+        let last = frame == frames;
+        let nth = options.every.is_some_and(|every| frame % every == 0);
+        if !last && !nth {
+            continue;
+        }
+
+        let (seconds, orbit_seconds) = match &recording {
+            Some(recording) => {
+                let keyframe = recording.frames[frame - 1];
+                camera.set_view(keyframe.view);
+                (keyframe.seconds, keyframe.orbit_seconds)
+            }
+            None => (frame as f32 * step, frame as f32 * step),
+        };
+        render_frame(&mut framebuffer, scene, &settings, seconds, orbit_seconds, &camera);
+        framebuffer.downsample(SUPERSAMPLE, &mut display);
+        if settings.bloom {
+            display.apply_bloom(settings.bloom_threshold, settings.bloom_intensity);
+        }
+        display.resolve();
+
+        let path = if options.every.is_some() {
+            numbered_path(&options.out, frame)
+        } else {
+            options.out.clone()
+        };
+        match display.save_png(&path) {
+            Ok(()) => println!("Saved {}", path),
+            Err(err) => {
+                eprintln!("Failed to save {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+// The whole program: parses the command line, then either renders headless
+// or opens the window. main.rs only calls this; everything lives in the
+// library so benches can reach render_frame.
+pub fn run() {
+    let options = parse_options();
+    let mut scene = load_scene(&options);
+    if options.headless {
+        run_headless(&options, &scene);
+        return;
+    }
+
+    let mut window_width = options.width;
+    let mut window_height = options.height;
+    let mut framebuffer_width = options.width * SUPERSAMPLE;
+    let mut framebuffer_height = options.height * SUPERSAMPLE;
+    let frame_delay = Duration::from_millis(16);
+
+    let mut framebuffer = Framebuffer::new(framebuffer_width, framebuffer_height);
+    let mut display = Framebuffer::new(window_width, window_height);
+    let mut window = Window::new(
+        "Animated Fragment Shader",
+        window_width,
+        window_height,
+        WindowOptions {
+            resize: true,
+            ..WindowOptions::default()
+        },
+    ).unwrap();
+
+    window.set_position(500, 500);
+    window.update();
+
+    framebuffer.set_background_gradient(BACKGROUND_TOP, BACKGROUND_BOTTOM);
+    display.set_gamma(options.gamma);
+    display.set_tone_mapping(options.tone_mapping);
+
+    let mut camera = create_camera();
+    let mut seconds = 0.0_f32;
+    let mut orbit_seconds = 0.0_f32;
+    let mut orbit_speed_scale = 1.0_f32;
+    let mut last_frame = Instant::now();
+    let mut fps_counter = FpsCounter::new();
+    let mut paused = false;
+    let mut settings = initial_settings(&options);
+    let mut light_yaw = 0.0_f32;
+    let mut light_pitch = 0.0_f32;
+    let mut free_fly = false;
+    let mut show_hud = false;
+    let mut tone_mapping = options.tone_mapping;
+    let mut saved_views: [Option<CameraView>; 9] = [None; 9];
+    let mut last_mouse_pos: Option<(f32, f32)> = None;
+    let mut mouse_was_down = false;
+    // Last body picked with a click, whose shader Q and E cycle
+    let mut selected: Option<usize> = None;
+    let mut last_readout: Option<String> = None;
+    // F9 records the camera and clocks every frame, F10 replays the recording
+    let mut recording: Option<Recording> = None;
+    let mut playback: Option<(Recording, usize)> = None;
+
+    while window.is_open() {
+        if window.is_key_down(Key::Escape) {
+            break;
+        }
+
+        
+        // Space also moves the camera up in free-fly mode, so it only
+        // pauses while orbiting
+        if !free_fly && window.is_key_pressed(Key::Space, KeyRepeat::No) {
+            paused = !paused;
+        }
+
+        let now = Instant::now();
+        let frame_time = now.duration_since(last_frame);
+        if window.is_key_pressed(Key::LeftBracket, KeyRepeat::Yes) {
+            orbit_speed_scale = (orbit_speed_scale - ORBIT_SPEED_STEP).max(0.0);
+            println!("Orbit speed: {:.2}x", orbit_speed_scale);
+        }
+        if window.is_key_pressed(Key::RightBracket, KeyRepeat::Yes) {
+            orbit_speed_scale = (orbit_speed_scale + ORBIT_SPEED_STEP).min(MAX_ORBIT_SPEED_SCALE);
+            println!("Orbit speed: {:.2}x", orbit_speed_scale);
+        }
+
+        let shift = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+        if shift && window.is_key_pressed(Key::Comma, KeyRepeat::Yes) {
+            settings.fov = (settings.fov - FOV_STEP).max(MIN_FOV);
+            println!("Field of view: {:.0}°", settings.fov);
+        }
+        if shift && window.is_key_pressed(Key::Period, KeyRepeat::Yes) {
+            settings.fov = (settings.fov + FOV_STEP).min(MAX_FOV);
+            println!("Field of view: {:.0}°", settings.fov);
+        }
+
+        let mut step = 0.0;
+        if !paused {
+            step = frame_time.as_secs_f32() * options.timescale;
+        } else if !shift && window.is_key_pressed(Key::Period, KeyRepeat::No) {
+            step = 1.0 / TICKS_PER_SECOND;
+        }
+        seconds += step;
+        // Accumulated rather than derived from `seconds`, so changing the
+        // scale changes how fast bodies move without making them jump
+        orbit_seconds += step * orbit_speed_scale;
+        last_frame = now;
+        let fps_updated = fps_counter.tick(frame_time);
+
+        
+        if window.is_key_pressed(Key::F, KeyRepeat::No) {
+            free_fly = !free_fly;
+            last_mouse_pos = None;
+        }
+
+        if free_fly {
+            if window.is_key_down(Key::W) {
+                camera.move_forward(FLY_SPEED);
+            }
+            if window.is_key_down(Key::S) {
+                camera.move_forward(-FLY_SPEED);
+            }
+            if window.is_key_down(Key::D) {
+                camera.move_right(FLY_SPEED);
+            }
+            if window.is_key_down(Key::A) {
+                camera.move_right(-FLY_SPEED);
+            }
+            if window.is_key_down(Key::Space) {
+                camera.move_up(FLY_SPEED);
+            }
+            if window.is_key_down(Key::LeftShift) {
+                camera.move_up(-FLY_SPEED);
+            }
+            if let Some((mouse_x, mouse_y)) = window.get_mouse_pos(MouseMode::Pass) {
+                if let Some((last_x, last_y)) = last_mouse_pos {
+                    camera.look(
+                        (mouse_x - last_x) * MOUSE_LOOK_SENSITIVITY,
+                        -(mouse_y - last_y) * MOUSE_LOOK_SENSITIVITY,
+                    );
+                }
+                last_mouse_pos = Some((mouse_x, mouse_y));
+            }
+        } else {
+            let obstacles: Vec<(Vec3, f32)> = body_spheres(&scene, &body_positions(&scene.bodies, orbit_seconds))
+                .into_iter()
+                .map(|(center, radius)| (center, radius + CAMERA_CLEARANCE))
+                .collect();
+
+            if window.is_key_down(Key::Left) {
+                camera.orbit(PI / 50.0, 0.0); 
+            }
+            if window.is_key_down(Key::Right) {
+                camera.orbit(-PI / 50.0, 0.0); 
+            }
+            if window.is_key_down(Key::Up) {
+                camera.orbit(0.0, -PI / 50.0); 
+            }
+            if window.is_key_down(Key::Down) {
+                camera.orbit(0.0, PI / 50.0); 
+            }
+            if window.is_key_down(Key::W) {
+                camera.zoom(0.1, &obstacles);
+            }
+            if window.is_key_down(Key::S) {
+                camera.zoom(-0.1, &obstacles);
+            }
+
+            // Left-drag orbits like the arrow keys: dragging right matches Right.
+            // Middle-drag pans, dragging the scene along with the cursor.
+            match window.get_mouse_pos(MouseMode::Pass) {
+                Some((mouse_x, mouse_y)) if window.get_mouse_down(MouseButton::Left) => {
+                    if let Some((last_x, last_y)) = last_mouse_pos {
+                        camera.orbit(
+                            -(mouse_x - last_x) * MOUSE_ORBIT_SENSITIVITY,
+                            (mouse_y - last_y) * MOUSE_ORBIT_SENSITIVITY,
+                        );
+                    }
+                    last_mouse_pos = Some((mouse_x, mouse_y));
+                }
+                Some((mouse_x, mouse_y)) if window.get_mouse_down(MouseButton::Middle) => {
+                    if let Some((last_x, last_y)) = last_mouse_pos {
+                        let scale = (camera.center - camera.eye).magnitude() * MOUSE_PAN_SENSITIVITY;
+                        camera.pan(-(mouse_x - last_x) * scale, (mouse_y - last_y) * scale);
+                    }
+                    last_mouse_pos = Some((mouse_x, mouse_y));
+                }
+                _ => last_mouse_pos = None,
+            }
+            if let Some((_, scroll_y)) = window.get_scroll_wheel() {
+                camera.zoom(scroll_y * SCROLL_ZOOM_SPEED, &obstacles);
+            }
+        }
+        if window.is_key_down(Key::J) {
+            light_yaw -= PI / 50.0;
+        }
+        if window.is_key_down(Key::L) {
+            light_yaw += PI / 50.0;
+        }
+        if window.is_key_down(Key::I) {
+            light_pitch += PI / 50.0;
+        }
+        if window.is_key_down(Key::K) {
+            light_pitch -= PI / 50.0;
+        }
+        if window.is_key_pressed(Key::U, KeyRepeat::No) {
+            settings.sun_lighting = !settings.sun_lighting;
+        }
+        if window.is_key_pressed(Key::O, KeyRepeat::No) {
+            settings.orthographic = !settings.orthographic;
+        }
+        if window.is_key_pressed(Key::C, KeyRepeat::No) {
+            settings.cull_backfaces = !settings.cull_backfaces;
+        }
+        if window.is_key_pressed(Key::M, KeyRepeat::No) {
+            settings.wireframe = !settings.wireframe;
+        }
+        if window.is_key_pressed(Key::N, KeyRepeat::No) {
+            settings.wireframe_overlay = !settings.wireframe_overlay;
+        }
+        if window.is_key_pressed(Key::V, KeyRepeat::No) {
+            settings.flat_shading = !settings.flat_shading;
+        }
+        if window.is_key_pressed(Key::T, KeyRepeat::No) {
+            tone_mapping = tone_mapping.next();
+            display.set_tone_mapping(tone_mapping);
+            println!("Tone mapping: {}", tone_mapping.name());
+        }
+        // Clicking reads the id buffer from the frame currently on screen
+        let mouse_down = window.get_mouse_down(MouseButton::Left);
+        if mouse_down && !mouse_was_down {
+            if let Some((mouse_x, mouse_y)) = window.get_mouse_pos(MouseMode::Discard) {
+                let (x, y) = (mouse_x as usize * SUPERSAMPLE, mouse_y as usize * SUPERSAMPLE);
+                selected = framebuffer.pick(x, y);
+                match selected {
+                    Some(index) => println!("Picked body {} (shader {})", index, scene.bodies[index].shader),
+                    None => println!("Nothing under the cursor"),
+                }
+            }
+        }
+        mouse_was_down = mouse_down;
+        // Q and E step the picked body's shader back and forth, wrapping
+        // around; the ring and atmosphere follow the new index next frame
+        if let Some(index) = selected {
+            let forward = if window.is_key_pressed(Key::E, KeyRepeat::Yes) {
+                Some(true)
+            } else if window.is_key_pressed(Key::Q, KeyRepeat::Yes) {
+                Some(false)
+            } else {
+                None
+            };
+            if let Some(forward) = forward {
+                let body = &mut scene.bodies[index];
+                body.shader = cycle_body_shader(body.shader, forward);
+                println!("Body {} shader {}", index, body.shader);
+            }
+        }
+        // Holding X reports the color, body and depth under the cursor in the
+        // frame on screen, printing again only when the readout changes
+        if window.is_key_down(Key::X) {
+            if let Some((mouse_x, mouse_y)) = window.get_mouse_pos(MouseMode::Discard) {
+                let (x, y) = (mouse_x as usize, mouse_y as usize);
+                if let Some(pixel) = display.get_pixel(x, y) {
+                    let (sample_x, sample_y) = (x * SUPERSAMPLE, y * SUPERSAMPLE);
+                    let body = framebuffer.pick(sample_x, sample_y).map_or(String::from("none"), |index| index.to_string());
+                    let readout = format!(
+                        "Pixel ({}, {}): rgb({}, {}, {}), body {}, depth {}",
+                        x, y, (pixel >> 16) & 0xFF, (pixel >> 8) & 0xFF, pixel & 0xFF, body, framebuffer.get_depth(sample_x, sample_y),
+                    );
+                    if last_readout.as_ref() != Some(&readout) {
+                        println!("{}", readout);
+                        last_readout = Some(readout);
+                    }
+                }
+            }
+        }
+        if window.is_key_pressed(Key::R, KeyRepeat::No) {
+            camera.reset();
+        }
+        // A number saves the current view in that slot, Shift+number glides
+        // back to it
+        for (slot, key) in VIEW_SLOT_KEYS.iter().enumerate() {
+            if window.is_key_pressed(*key, KeyRepeat::No) {
+                if !shift {
+                    saved_views[slot] = Some(camera.view());
+                } else if let Some(view) = saved_views[slot] {
+                    camera.transition_to(view, VIEW_TRANSITION_FRAMES);
+                }
+            }
+        }
+        // +/- tune the shaders' noise zoom, or their speed with Shift; 0 resets
+        let noise_step = if window.is_key_pressed(Key::Equal, KeyRepeat::Yes) || window.is_key_pressed(Key::NumPadPlus, KeyRepeat::Yes) {
+            Some(NOISE_PARAM_STEP)
+        } else if window.is_key_pressed(Key::Minus, KeyRepeat::Yes) || window.is_key_pressed(Key::NumPadMinus, KeyRepeat::Yes) {
+            Some(1.0 / NOISE_PARAM_STEP)
+        } else {
+            None
+        };
+        let noise_reset = window.is_key_pressed(Key::Key0, KeyRepeat::No);
+        if noise_step.is_some() || noise_reset {
+            let params = &mut settings.noise_params;
+            match noise_step {
+                Some(step) if shift => params.speed *= step,
+                Some(step) => params.zoom *= step,
+                None => *params = NoiseParams::default(),
+            }
+            println!("Noise zoom x{:.2}, speed x{:.2}", params.zoom, params.speed);
+        }
+        camera.update();
+        if window.is_key_pressed(Key::H, KeyRepeat::No) {
+            settings.fog = !settings.fog;
+        }
+        if window.is_key_pressed(Key::B, KeyRepeat::No) {
+            settings.bloom = !settings.bloom;
+        }
+        if window.is_key_pressed(Key::Z, KeyRepeat::No) {
+            settings.debug_depth = !settings.debug_depth;
+        }
+        if window.is_key_pressed(Key::G, KeyRepeat::No) {
+            settings.show_grid = !settings.show_grid;
+        }
+        if window.is_key_pressed(Key::Y, KeyRepeat::No) {
+            settings.show_orbits = !settings.show_orbits;
+        }
+        if window.is_key_down(Key::NumPad1) {
+            settings.light_pos.x -= POINT_LIGHT_STEP;
+        }
+        if window.is_key_down(Key::NumPad2) {
+            settings.light_pos.x += POINT_LIGHT_STEP;
+        }
+        if window.is_key_down(Key::NumPad3) {
+            settings.light_pos.y -= POINT_LIGHT_STEP;
+        }
+        if window.is_key_down(Key::NumPad4) {
+            settings.light_pos.y += POINT_LIGHT_STEP;
+        }
+        if window.is_key_down(Key::NumPad5) {
+            settings.light_pos.z -= POINT_LIGHT_STEP;
+        }
+        if window.is_key_down(Key::NumPad6) {
+            settings.light_pos.z += POINT_LIGHT_STEP;
+        }
+        light_pitch = light_pitch.clamp(-PI / 2.0 + 0.1, PI / 2.0 - 0.1);
+        settings.light_dir = light_direction(light_yaw, light_pitch);
+
+        if window.is_key_pressed(Key::F9, KeyRepeat::No) {
+            match recording.take() {
+                Some(finished) => match finished.save(RECORDING_PATH) {
+                    Ok(()) => println!("Saved {} frames to {}", finished.frames.len(), RECORDING_PATH),
+                    Err(err) => eprintln!("Failed to save {}: {}", RECORDING_PATH, err),
+                },
+                None => {
+                    playback = None;
+                    recording = Some(Recording::default());
+                    println!("Recording");
+                }
+            }
+        }
+        if window.is_key_pressed(Key::F10, KeyRepeat::No) {
+            if playback.take().is_some() {
+                println!("Playback stopped");
+            } else if recording.is_none() {
+                match Recording::load(RECORDING_PATH) {
+                    Ok(loaded) => {
+                        println!("Playing {} frames", loaded.frames.len());
+                        playback = Some((loaded, 0));
+                    }
+                    Err(err) => eprintln!("Failed to load recording: {}", err),
+                }
+            }
+        }
+        // Playback overrides whatever the camera and time input did above
+        if let Some((loaded, frame)) = playback.as_mut() {
+            match loaded.frames.get(*frame) {
+                Some(keyframe) => {
+                    camera.set_view(keyframe.view);
+                    seconds = keyframe.seconds;
+                    orbit_seconds = keyframe.orbit_seconds;
+                    *frame += 1;
+                }
+                None => {
+                    println!("Playback finished");
+                    playback = None;
+                }
+            }
+        }
+        if let Some(recording) = recording.as_mut() {
+            recording.frames.push(Keyframe { seconds, orbit_seconds, view: camera.view() });
+        }
+
+        let (new_width, new_height) = window.get_size();
+        if new_width == 0 || new_height == 0 {
+            // Minimized: keep the old buffers and just pump events
+            window.update();
+            std::thread::sleep(frame_delay);
+            continue;
+        }
+        if new_width != window_width || new_height != window_height {
+            window_width = new_width;
+            window_height = new_height;
+            framebuffer_width = new_width * SUPERSAMPLE;
+            framebuffer_height = new_height * SUPERSAMPLE;
+            framebuffer.resize(framebuffer_width, framebuffer_height);
+            display.resize(window_width, window_height);
+        }
+
+        let stats = render_frame(&mut framebuffer, &scene, &settings, seconds, orbit_seconds, &camera);
+
+        framebuffer.downsample(SUPERSAMPLE, &mut display);
+        if settings.bloom {
+            display.apply_bloom(settings.bloom_threshold, settings.bloom_intensity);
+        }
+        display.resolve();
+
+        if window.is_key_pressed(Key::P, KeyRepeat::No) {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis())
+                .unwrap_or(0);
+            let path = format!("screenshot_{}.png", timestamp);
+            match display.save_png(&path) {
+                Ok(()) => println!("Saved {}", path),
+                Err(err) => eprintln!("Failed to save {}: {}", path, err),
+            }
+        }
+
+        // After the screenshot, so saved images stay clean
+        if window.is_key_pressed(Key::Tab, KeyRepeat::No) {
+            show_hud = !show_hud;
+        }
+        if free_fly {
+            display.draw_crosshair(CROSSHAIR_SIZE, HUD_COLOR);
+        }
+        if show_hud {
+            let eye = camera.eye;
+            let text = format!("CAMERA {:.2} {:.2} {:.2}", eye.x, eye.y, eye.z);
+            display.draw_text(HUD_MARGIN, HUD_MARGIN, &text, HUD_COLOR);
+        }
+
+        // Stats change every frame; without them the title only needs
+        // refreshing when the FPS average does
+        if cfg!(feature = "stats") || fps_updated {
+            let mut title = format!(
+                "Animated Fragment Shader — {:.0} fps ({:.1} ms)",
+                fps_counter.fps(),
+                fps_counter.frame_ms(),
+            );
+            if cfg!(feature = "stats") {
+                title.push_str(&format!(
+                    " | objects culled {} | tris {} (culled {}, clipped {}) | frags {} (depth failed {})",
+                    stats.objects_culled,
+                    stats.triangles_submitted,
+                    stats.triangles_culled,
+                    stats.triangles_clipped,
+                    stats.fragments_shaded,
+                    stats.fragments_depth_failed,
+                ));
+            }
+            window.set_title(&title);
+        }
+
+        display.present();
+        if let Err(err) = window.update_with_buffer(display.front(), window_width, window_height) {
+            eprintln!("Failed to update window: {}", err);
+            break;
+        }
+
+        std::thread::sleep(frame_delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One frame of the built-in scene, small enough for a debug build
+    #[cfg(feature = "parallel")]
+    fn render_test_frame(scene: &Scene, settings: &RenderSettings) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(320, 240);
+        framebuffer.set_background_gradient(BACKGROUND_TOP, BACKGROUND_BOTTOM);
+        render_frame(&mut framebuffer, scene, settings, 1.0, 1.0, &create_camera());
+        framebuffer
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_shading_matches_serial() {
+        let options = Options::default();
+        let scene = load_scene(&options);
+        let serial = render_test_frame(&scene, &RenderSettings { parallel: false, ..initial_settings(&options) });
+        let parallel = render_test_frame(&scene, &RenderSettings { parallel: true, ..initial_settings(&options) });
+
+        assert_eq!(serial.hdr, parallel.hdr);
+        assert_eq!(serial.zbuffer, parallel.zbuffer);
+        assert_eq!(serial.ids, parallel.ids);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_vertex_shading_matches_serial() {
+        let options = Options::default();
+        let scene = load_scene(&options);
+        let uniforms = Uniforms {
+            model_matrix: create_model_matrix(Vec3::new(0.5, 0.0, 0.0), 0.7, Vec3::new(0.3, 1.1, 0.0)),
+            ..Uniforms::for_test(320.0, 240.0)
+        };
+        let vertices = &scene.models[0].lods[0];
+        let serial = transform_vertices(vertices, &uniforms);
+        let parallel = transform_vertices_parallel(vertices, &uniforms);
+
+        assert_eq!(serial.len(), parallel.len());
+        for (a, b) in serial.iter().zip(&parallel) {
+            assert_eq!(a.clip_position, b.clip_position);
+            assert_eq!(a.transformed_normal, b.transformed_normal);
+            assert_eq!(a.world_position, b.world_position);
+        }
+    }
+
+    #[test]
+    fn degenerate_triangles_leave_no_nan() {
+        let uniforms = Uniforms::for_test(64.0, 48.0);
+        let eye = create_camera().eye;
+        let vertex = |position: Vec3| Vertex::new(position, Vec3::new(0.0, 1.0, 0.0), nalgebra_glm::Vec2::zeros());
+        let vertices = vec![
+            // Coincident corners
+            vertex(Vec3::new(0.2, 0.1, 0.0)),
+            vertex(Vec3::new(0.2, 0.1, 0.0)),
+            vertex(Vec3::new(0.2, 0.1, 0.0)),
+            // Collinear corners
+            vertex(Vec3::new(-0.5, 0.0, 0.0)),
+            vertex(Vec3::new(0.0, 0.0, 0.0)),
+            vertex(Vec3::new(0.5, 0.0, 0.0)),
+            // One corner on the eye, where clip-space w is 0
+            vertex(eye),
+            vertex(Vec3::new(-0.5, 0.0, 0.0)),
+            vertex(Vec3::new(0.5, 0.5, 0.0)),
+        ];
+        assert!(vertex_shader(&vertices[6], &uniforms).clip_position.w.abs() < 1e-5);
+
+        let mut framebuffer = Framebuffer::new(64, 48);
+        framebuffer.clear();
+        render(&mut framebuffer, &uniforms, &vertices, 1, &mut RenderStats::default());
+
+        assert!(framebuffer.hdr.iter().all(|pixel| pixel.iter().all(|channel| channel.is_finite())));
+        assert!(framebuffer.zbuffer.iter().all(|depth| !depth.is_nan()));
+    }
+
+    #[test]
+    fn screen_mapping_survives_zero_w() {
+        let uniforms = Uniforms::for_test(64.0, 48.0);
+        let mut vertex = Vertex::new(Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0), nalgebra_glm::Vec2::zeros());
+        vertex.clip_position = Vec4::new(0.0, 0.0, -0.1, 0.0);
+        screen_mapping(&mut vertex, &uniforms);
+        assert!(vertex.transformed_position.iter().all(|value| value.is_finite()));
+    }
+
+    // What render() counted drawing the sphere model into a 320 x 240 frame
+    #[cfg(feature = "stats")]
+    fn sphere_render_stats(cull_backfaces: bool) -> RenderStats {
+        let uniforms = Uniforms { cull_backfaces, ..Uniforms::for_test(320.0, 240.0) };
+        let vertices = Obj::load("assets/models/sphere.obj").unwrap().get_vertex_array();
+        let mut framebuffer = Framebuffer::new(320, 240);
+        let mut stats = RenderStats::default();
+        render(&mut framebuffer, &uniforms, &vertices, 0, &mut stats);
+        stats
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn backface_culling_halves_sphere_fragments() {
+        let (all, culled) = (sphere_render_stats(false), sphere_render_stats(true));
+        // The back half covers as much of the screen as the front one, so
+        // with this camera the 1996 fragments drop to 998
+        assert_eq!(all.triangles_culled, 0);
+        assert!(culled.triangles_culled > 0);
+        assert_eq!(all.fragments_shaded, 1996);
+        assert_eq!(culled.fragments_shaded, 998);
+    }
+
+    #[cfg(feature = "stats")]
+    fn built_in_frame_stats(settings: &RenderSettings, camera: &Camera) -> RenderStats {
+        let scene = load_scene(&Options::default());
+        let mut framebuffer = Framebuffer::new(320, 240);
+        render_frame(&mut framebuffer, &scene, settings, 1.0, 1.0, camera)
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats_count_backface_culling() {
+        let options = Options::default();
+        let culled = built_in_frame_stats(&RenderSettings { cull_backfaces: true, ..initial_settings(&options) }, &create_camera());
+        let unculled = built_in_frame_stats(&RenderSettings { cull_backfaces: false, ..initial_settings(&options) }, &create_camera());
+
+        assert!(culled.fragments_shaded < unculled.fragments_shaded);
+        assert!(culled.triangles_culled > unculled.triangles_culled);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats_count_frustum_culled_bodies() {
+        let settings = initial_settings(&Options::default());
+        let facing = built_in_frame_stats(&settings, &create_camera());
+        // Same eye, looking straight away from the system
+        let away = Camera::new(Vec3::new(0.0, 3.0, 5.0), Vec3::new(0.0, 3.0, 10.0), Vec3::new(0.0, 1.0, 0.0));
+        let turned = built_in_frame_stats(&settings, &away);
+
+        assert!(turned.objects_culled > facing.objects_culled);
+        assert!(turned.fragments_shaded < facing.fragments_shaded);
+    }
+
+    // Pixels the grid drew into a 160 x 120 frame from the default camera
+    fn fogged_grid_pixels(density: f32) -> Vec<Vec3> {
+        let camera = create_camera();
+        let view_matrix = create_view_matrix(camera.eye, camera.center, camera.up);
+        let projection_matrix = create_perspective_matrix(160.0, 120.0, DEFAULT_FOV, 0.1, 1000.0);
+        let fog = LineFog { color: Vec3::new(0.2, 0.4, 0.6), density, near: 0.1, far: 1000.0, projection_matrix: &projection_matrix };
+
+        let mut framebuffer = Framebuffer::new(160, 120);
+        framebuffer.clear();
+        draw_grid(&mut framebuffer, &(projection_matrix * view_matrix), &create_viewport_matrix(160.0, 120.0), &fog);
+        framebuffer.hdr.iter()
+            .zip(&framebuffer.zbuffer)
+            .filter(|(_, depth)| depth.is_finite())
+            .map(|(pixel, _)| *pixel)
+            .collect()
+    }
+
+    #[test]
+    fn grid_fades_into_fog() {
+        let grid_colors = [Color::new(60, 60, 90).to_rgb(), Color::new(140, 140, 190).to_rgb()];
+        let clear = fogged_grid_pixels(0.0);
+        assert!(!clear.is_empty());
+        assert!(clear.iter().all(|pixel| grid_colors.contains(pixel)));
+
+        // Every grid line is a few units from the eye, far enough for this
+        // density to leave nothing of the line color
+        let fog = Color::from_rgb(&Vec3::new(0.2, 0.4, 0.6)).to_rgb();
+        assert!(fogged_grid_pixels(10.0).iter().all(|pixel| *pixel == fog));
+    }
+
+    #[test]
+    fn shader_cycling_skips_ring_and_atmosphere() {
+        let reserved = [RING_SHADER_INDEX, ATMOSPHERE_SHADER_INDEX];
+        for forward in [true, false] {
+            let mut shader = 0;
+            let mut seen = Vec::new();
+            for _ in 0..3 * SHADER_COUNT {
+                shader = cycle_body_shader(shader, forward);
+                assert!(!reserved.contains(&shader), "cycled onto shader {}", shader);
+                seen.push(shader);
+            }
+            seen.sort();
+            seen.dedup();
+            assert_eq!(seen.len(), SHADER_COUNT - reserved.len());
+        }
+        // A scene file can ask for an index past the last shader
+        assert!(cycle_body_shader(SHADER_COUNT + 4, true) < SHADER_COUNT);
+    }
+}
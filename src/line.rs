@@ -1,6 +1,5 @@
 use crate::fragment::Fragment;
 use crate::vertex::Vertex;
-use crate::color::Color;
 
 pub fn line(a: &Vertex, b: &Vertex) -> Vec<Fragment> {
     let mut fragments = Vec::new();
@@ -20,10 +19,18 @@ pub fn line(a: &Vertex, b: &Vertex) -> Vec<Fragment> {
     let sy = if y0 < y1 { 1 } else { -1 };
 
     let mut err = if dx > dy { dx / 2 } else { -dy / 2 };
+    let steps = dx.max(dy).max(1) as f32;
+    let mut step = 0;
 
     loop {
-        let z = start.z + (end.z - start.z) * (x0 - start.x as i32) as f32 / (end.x - start.x) as f32;
-        fragments.push(Fragment::new(x0 as f32, y0 as f32, Color::new(255, 255, 255), z));
+        let t = step as f32 / steps;
+        let z = start.z + (end.z - start.z) * t;
+        let color = a.color.lerp(&b.color, t);
+        let position = a.position + (b.position - a.position) * t;
+        let normal = a.transformed_normal + (b.transformed_normal - a.transformed_normal) * t;
+        let mut fragment = Fragment::new(x0 as f32, y0 as f32, color, z, normal, position);
+        fragment.world_position = a.world_position + (b.world_position - a.world_position) * t;
+        fragments.push(fragment);
 
         if x0 == x1 && y0 == y1 { break; }
 
@@ -36,6 +43,7 @@ pub fn line(a: &Vertex, b: &Vertex) -> Vec<Fragment> {
             err += dx;
             y0 += sy;
         }
+        step += 1;
     }
 
     fragments
@@ -0,0 +1,43 @@
+use crate::color::Color;
+
+// Per-body surface parameters. Procedural shaders may compute their own
+// albedo, but specular response and emission always come from here.
+// `roughness` in [0, 1] dims the highlight: 0 is a full mirror-like
+// highlight, 1 removes it. `emission` scales an emissive surface's output;
+// past 1 it is brighter than white and relies on tone mapping to show.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    pub base_color: Color,
+    pub specular_color: Color,
+    pub shininess: f32,
+    pub roughness: f32,
+    pub emissive: bool,
+    pub emission: f32,
+}
+
+impl Material {
+    pub const fn new(base_color: Color, specular_color: Color, shininess: f32, roughness: f32, emissive: bool) -> Self {
+        Material {
+            base_color,
+            specular_color,
+            shininess,
+            roughness,
+            emissive,
+            emission: 1.0,
+        }
+    }
+
+    // Diffuse only, no highlight
+    pub const fn matte(base_color: Color) -> Self {
+        Material::new(base_color, Color::black(), 1.0, 1.0, false)
+    }
+
+    pub const fn emissive(base_color: Color) -> Self {
+        Material::new(base_color, Color::black(), 1.0, 1.0, true)
+    }
+
+    pub const fn with_emission(mut self, emission: f32) -> Self {
+        self.emission = emission;
+        self
+    }
+}
@@ -1,29 +1,71 @@
-use tobj;
 use nalgebra_glm::{Vec2, Vec3};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use crate::vertex::Vertex;
+use crate::color::Color;
 
+// Finest grid tried by `Obj::decimate`, in cells per axis
+const MAX_CLUSTER_RESOLUTION: usize = 64;
+
+#[derive(Clone)]
 pub struct Obj {
     meshes: Vec<Mesh>,
+    materials: Vec<ObjMaterial>,
+    // Segments from `l` elements, in model space. A polyline `l a b c` is
+    // split into a-b and b-c.
+    lines: Vec<[Vec3; 2]>,
+}
+
+// The parts of an MTL material the renderer understands
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub diffuse: Color,
+    pub diffuse_texture: Option<String>,
 }
 
+// tobj splits a model at every `usemtl`, so all faces of a mesh share the
+// same material. `material_id` indexes into `Obj::materials`.
+#[derive(Clone)]
 struct Mesh {
     vertices: Vec<Vec3>,
     normals: Vec<Vec3>,
     texcoords: Vec<Vec2>,
     indices: Vec<u32>,
+    material_id: Option<usize>,
 }
 
 impl Obj {
+    // Quads and longer polygons are fan-triangulated by tobj (a,b,c then
+    // a,c,d, ...). With `single_index` every corner becomes its own
+    // position/uv/normal triple, so the attributes stay aligned across the split.
+    // tobj would turn `l` elements into degenerate or fan triangles, so they
+    // are taken out of the source and parsed separately.
     pub fn load(filename: &str) -> Result<Self, tobj::LoadError> {
-        let (models, _) = tobj::load_obj(filename, &tobj::LoadOptions {
+        let source = fs::read_to_string(filename).map_err(|_| tobj::LoadError::OpenFileFailed)?;
+        let (face_source, lines) = split_lines(&source);
+
+        let base = Path::new(filename).parent().unwrap_or(Path::new(""));
+        let (models, materials) = tobj::load_obj_buf(&mut face_source.as_bytes(), &tobj::LoadOptions {
             single_index: true,
             triangulate: true,
             ..Default::default()
-        })?;
+        }, |material_path| tobj::load_mtl(base.join(material_path)))?;
+
+        // A missing or broken MTL file is not fatal, faces just fall back to white
+        let materials = match materials {
+            Ok(materials) => materials.into_iter().map(ObjMaterial::from).collect(),
+            Err(err) => {
+                eprintln!("Could not load materials for {}: {}", filename, err);
+                Vec::new()
+            }
+        };
 
         let meshes = models.into_iter().map(|model| {
             let mesh = model.mesh;
-            Mesh {
+            let mut mesh = Mesh {
                 vertices: mesh.positions.chunks(3)
                     .map(|v| Vec3::new(v[0], v[1], v[2]))
                     .collect(),
@@ -31,19 +73,87 @@ impl Obj {
                     .map(|n| Vec3::new(n[0], n[1], n[2]))
                     .collect(),
                 texcoords: mesh.texcoords.chunks(2)
-                    .map(|t| Vec2::new(t[0], 1.0 - t[1]))
+                    .map(|t| Vec2::new(t[0], t[1]))
                     .collect(),
                 indices: mesh.indices,
+                material_id: mesh.material_id,
+            };
+            if mesh.normals.is_empty() {
+                mesh.compute_normals();
             }
+            mesh
         }).collect();
 
-        Ok(Obj { meshes })
+        Ok(Obj { meshes, materials, lines })
+    }
+
+    #[allow(dead_code)]
+    pub fn materials(&self) -> &[ObjMaterial] {
+        &self.materials
+    }
+
+    // Min and max corners over every mesh. An empty model gives an inverted
+    // box (min at +INFINITY, max at -INFINITY).
+    pub fn bounding_box(&self) -> (Vec3, Vec3) {
+        let mut min = Vec3::repeat(f32::INFINITY);
+        let mut max = Vec3::repeat(f32::NEG_INFINITY);
+        for vertex in self.meshes.iter().flat_map(|mesh| &mesh.vertices) {
+            min = min.inf(vertex);
+            max = max.sup(vertex);
+        }
+        (min, max)
+    }
+
+    // Moves the bounding box center to the origin and scales uniformly so the
+    // farthest vertex lands on the unit sphere. Normals are unaffected.
+    pub fn normalize(&mut self) {
+        let (min, max) = self.bounding_box();
+        if min.x > max.x {
+            return;
+        }
+        let center = (min + max) * 0.5;
+
+        let radius = self.meshes.iter()
+            .flat_map(|mesh| &mesh.vertices)
+            .map(|vertex| (vertex - center).magnitude())
+            .fold(0.0, f32::max);
+        let scale = if radius > 0.0 { 1.0 / radius } else { 1.0 };
+
+        let line_points = self.lines.iter_mut().flat_map(|line| line.iter_mut());
+        for vertex in self.meshes.iter_mut().flat_map(|mesh| mesh.vertices.iter_mut()).chain(line_points) {
+            *vertex = (*vertex - center) * scale;
+        }
+    }
+
+    // Vertex-clustering decimation: snaps vertices to a uniform grid over the
+    // mesh bounds and merges each cell into one averaged vertex, dropping the
+    // triangles that collapse. Uses the finest grid that fits in `target_tris`;
+    // if even a 2x2x2 grid doesn't, the model comes back unchanged rather
+    // than collapsed to nothing.
+    pub fn decimate(&self, target_tris: usize) -> Obj {
+        for resolution in (2..=MAX_CLUSTER_RESOLUTION).rev() {
+            let meshes: Vec<Mesh> = self.meshes.iter()
+                .map(|mesh| mesh.cluster(resolution))
+                .collect();
+            let triangle_count: usize = meshes.iter().map(|mesh| mesh.indices.len() / 3).sum();
+
+            if triangle_count <= target_tris {
+                return Obj { meshes, materials: self.materials.clone(), lines: self.lines.clone() };
+            }
+        }
+
+        self.clone()
     }
 
     pub fn get_vertex_array(&self) -> Vec<Vertex> {
         let mut vertices = Vec::new();
 
         for mesh in &self.meshes {
+            let color = mesh.material_id
+                .and_then(|id| self.materials.get(id))
+                .map(|material| material.diffuse)
+                .unwrap_or(Color::new(255, 255, 255));
+
             for &index in &mesh.indices {
                 let position = mesh.vertices[index as usize];
                 let normal = mesh.normals.get(index as usize)
@@ -53,10 +163,264 @@ impl Obj {
                     .cloned()
                     .unwrap_or(Vec2::new(0.0, 0.0));
 
-                vertices.push(Vertex::new(position, normal, tex_coords));
+                let mut vertex = Vertex::new(position, normal, tex_coords);
+                vertex.color = color;
+                vertices.push(vertex);
             }
         }
 
         vertices
     }
+
+    // The `l` segments as vertex pairs, white and without normals. Lines
+    // aren't decimated, so every level of detail returns the same ones.
+    pub fn get_line_array(&self) -> Vec<[Vertex; 2]> {
+        let white = Color::new(255, 255, 255);
+        self.lines.iter()
+            .map(|&[start, end]| [Vertex::new_with_color(start, white), Vertex::new_with_color(end, white)])
+            .collect()
+    }
+}
+
+// Returns the OBJ source without its `l` statements, and those statements as
+// segments. Indices refer to the `v` positions read so far (1-based, or
+// negative counting back from the latest); any /vt part is ignored, as are
+// indices that point nowhere.
+fn split_lines(source: &str) -> (String, Vec<[Vec3; 2]>) {
+    let mut faces = String::with_capacity(source.len());
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut lines = Vec::new();
+
+    for statement in source.lines() {
+        let mut words = statement.split_whitespace();
+        match words.next() {
+            Some("l") => {
+                let points: Vec<Vec3> = words
+                    .filter_map(|word| word.split('/').next()?.parse::<i64>().ok())
+                    .filter_map(|index| {
+                        let index = if index < 0 { positions.len() as i64 + index } else { index - 1 };
+                        positions.get(usize::try_from(index).ok()?).copied()
+                    })
+                    .collect();
+                lines.extend(points.windows(2).map(|pair| [pair[0], pair[1]]));
+                continue;
+            }
+            Some("v") => {
+                let coordinates: Vec<f32> = words.take(3).filter_map(|word| word.parse().ok()).collect();
+                if let [x, y, z] = coordinates[..] {
+                    positions.push(Vec3::new(x, y, z));
+                }
+            }
+            _ => {}
+        }
+        faces.push_str(statement);
+        faces.push('\n');
+    }
+
+    (faces, lines)
+}
+
+impl From<tobj::Material> for ObjMaterial {
+    fn from(material: tobj::Material) -> Self {
+        let [r, g, b] = material.diffuse.unwrap_or([1.0, 1.0, 1.0]);
+        let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        ObjMaterial {
+            name: material.name,
+            diffuse: Color::new(channel(r), channel(g), channel(b)),
+            diffuse_texture: material.diffuse_texture,
+        }
+    }
+}
+
+impl Mesh {
+    // Smooth normals from the area-weighted average of the adjacent face
+    // normals. Faces are grouped by position rather than index, because
+    // single-index loading duplicates vertices along UV seams.
+    fn compute_normals(&mut self) {
+        let key = |v: &Vec3| (v.x.to_bits(), v.y.to_bits(), v.z.to_bits());
+        let mut sums: HashMap<(u32, u32, u32), Vec3> = HashMap::new();
+
+        for face in self.indices.chunks(3) {
+            if face.len() < 3 {
+                continue;
+            }
+            let a = self.vertices[face[0] as usize];
+            let b = self.vertices[face[1] as usize];
+            let c = self.vertices[face[2] as usize];
+            let face_normal = (b - a).cross(&(c - a));
+
+            for vertex in [a, b, c] {
+                *sums.entry(key(&vertex)).or_insert_with(Vec3::zeros) += face_normal;
+            }
+        }
+
+        self.normals = self.vertices.iter()
+            .map(|vertex| {
+                let sum = sums.get(&key(vertex)).cloned().unwrap_or_else(Vec3::zeros);
+                sum.try_normalize(f32::EPSILON).unwrap_or(Vec3::new(0.0, 1.0, 0.0))
+            })
+            .collect();
+    }
+
+    fn cluster(&self, resolution: usize) -> Mesh {
+        let mut min = Vec3::repeat(f32::INFINITY);
+        let mut max = Vec3::repeat(f32::NEG_INFINITY);
+        for vertex in &self.vertices {
+            min = min.inf(vertex);
+            max = max.sup(vertex);
+        }
+        let extent = max - min;
+
+        let cell_index = |value: f32, low: f32, size: f32| -> usize {
+            if size > 0.0 {
+                (((value - low) / size) * resolution as f32).min(resolution as f32 - 1.0) as usize
+            } else {
+                0
+            }
+        };
+
+        let mut cells: HashMap<(usize, usize, usize), u32> = HashMap::new();
+        let mut sums: Vec<(Vec3, Vec3, Vec2, f32)> = Vec::new();
+        let mut remap = Vec::with_capacity(self.vertices.len());
+
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            let key = (
+                cell_index(vertex.x, min.x, extent.x),
+                cell_index(vertex.y, min.y, extent.y),
+                cell_index(vertex.z, min.z, extent.z),
+            );
+            let next_id = sums.len() as u32;
+            let id = *cells.entry(key).or_insert(next_id);
+            if id == next_id {
+                sums.push((Vec3::zeros(), Vec3::zeros(), Vec2::zeros(), 0.0));
+            }
+
+            let sum = &mut sums[id as usize];
+            sum.0 += vertex;
+            if let Some(normal) = self.normals.get(i) {
+                sum.1 += normal;
+            }
+            if let Some(tex_coords) = self.texcoords.get(i) {
+                sum.2 += tex_coords;
+            }
+            sum.3 += 1.0;
+            remap.push(id);
+        }
+
+        let vertices = sums.iter().map(|sum| sum.0 / sum.3).collect();
+        let normals = if self.normals.is_empty() {
+            Vec::new()
+        } else {
+            sums.iter()
+                .map(|sum| if sum.1.magnitude() > 0.0 { sum.1.normalize() } else { sum.1 })
+                .collect()
+        };
+        let texcoords = if self.texcoords.is_empty() {
+            Vec::new()
+        } else {
+            sums.iter().map(|sum| sum.2 / sum.3).collect()
+        };
+
+        let mut indices = Vec::with_capacity(self.indices.len());
+        for face in self.indices.chunks(3) {
+            let (a, b, c) = (remap[face[0] as usize], remap[face[1] as usize], remap[face[2] as usize]);
+            if a != b && b != c && a != c {
+                indices.extend_from_slice(&[a, b, c]);
+            }
+        }
+
+        Mesh { vertices, normals, texcoords, indices, material_id: self.material_id }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_quad_without_normals_gets_one_normal() {
+        let vertices = Obj::load("assets/models/quad.obj").unwrap().get_vertex_array();
+        assert_eq!(vertices.len(), 6);
+        for vertex in &vertices {
+            assert!((vertex.normal - Vec3::new(0.0, 0.0, 1.0)).magnitude() < 1e-6, "{:?}", vertex.normal);
+        }
+    }
+
+    #[test]
+    fn quad_face_splits_into_two_triangles() {
+        let positions: Vec<Vec3> = Obj::load("assets/models/quad.obj").unwrap()
+            .get_vertex_array()
+            .iter()
+            .map(|vertex| vertex.position)
+            .collect();
+        let [a, b, c, d] = [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(-1.0, 1.0, 0.0),
+        ];
+        assert_eq!(positions, vec![a, b, c, a, c, d]);
+    }
+
+    #[test]
+    fn two_material_cube_colors_faces_by_material() {
+        let obj = Obj::load("assets/models/two_material_cube.obj").unwrap();
+        let names: Vec<&str> = obj.materials().iter().map(|material| material.name.as_str()).collect();
+        assert_eq!(names, ["red", "blue"]);
+
+        // Three quads per material, two triangles each, red first
+        let vertices = obj.get_vertex_array();
+        assert_eq!(vertices.len(), 36);
+        assert!(vertices[..18].iter().all(|vertex| vertex.color == Color::new(204, 26, 26)));
+        assert!(vertices[18..].iter().all(|vertex| vertex.color == Color::new(26, 51, 204)));
+    }
+
+    #[test]
+    fn missing_mtl_falls_back_to_white() {
+        let obj = Obj::load("assets/models/missing_material.obj").unwrap();
+        assert!(obj.materials().is_empty());
+        let vertices = obj.get_vertex_array();
+        assert_eq!(vertices.len(), 3);
+        assert!(vertices.iter().all(|vertex| vertex.color == Color::new(255, 255, 255)));
+    }
+
+    #[test]
+    fn texture_coordinates_are_kept_exactly() {
+        let vertices = Obj::load("assets/models/uv_triangle.obj").unwrap().get_vertex_array();
+        assert_eq!(vertices[0].tex_coords, Vec2::new(0.25, 0.75));
+    }
+
+    #[test]
+    fn normalize_recenters_an_offset_cube() {
+        let mut obj = Obj::load("assets/models/two_material_cube.obj").unwrap();
+        for vertex in obj.meshes.iter_mut().flat_map(|mesh| mesh.vertices.iter_mut()) {
+            *vertex = *vertex * 4.0 + Vec3::new(10.0, -3.0, 7.0);
+        }
+        obj.normalize();
+
+        let (min, max) = obj.bounding_box();
+        assert!(((min + max) * 0.5).magnitude() < 1e-5);
+        let extent = obj.meshes.iter()
+            .flat_map(|mesh| &mesh.vertices)
+            .map(|vertex| vertex.magnitude())
+            .fold(0.0, f32::max);
+        assert!((extent - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn decimate_reduces_sphere_triangles() {
+        let sphere = Obj::load("assets/models/sphere.obj").unwrap();
+        let full = sphere.get_vertex_array().len() / 3;
+        let decimated = sphere.decimate(200).get_vertex_array().len() / 3;
+        assert!(decimated <= 200 && decimated > 0, "{} of {} triangles", decimated, full);
+        assert!(decimated * 2 < full, "{} of {} triangles", decimated, full);
+    }
+
+    #[test]
+    fn decimate_keeps_model_when_budget_is_unreachable() {
+        let sphere = Obj::load("assets/models/sphere.obj").unwrap();
+        let full = sphere.get_vertex_array().len();
+        assert_eq!(sphere.decimate(0).get_vertex_array().len(), full);
+    }
 }
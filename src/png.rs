@@ -0,0 +1,180 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// Minimal PNG encoder for 8-bit RGB images. The image data goes into stored
+// (uncompressed) deflate blocks, which keeps this dependency free at the cost
+// of file size.
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+const MAX_STORED_BLOCK: usize = 65535;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk(writer: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+
+    let mut crc_input = Vec::with_capacity(kind.len() + data.len());
+    crc_input.extend_from_slice(kind);
+    crc_input.extend_from_slice(data);
+    writer.write_all(&crc32(&crc_input).to_be_bytes())
+}
+
+fn zlib_stored(raw: &[u8]) -> Vec<u8> {
+    let block_count = raw.len().div_ceil(MAX_STORED_BLOCK);
+    let mut out = Vec::with_capacity(raw.len() + block_count * 5 + 6);
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = raw.chunks(MAX_STORED_BLOCK).peekable();
+    while let Some(block) = blocks.next() {
+        let is_last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(is_last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(raw).to_be_bytes());
+    out
+}
+
+// `rows` holds `height` rows of `width * 3` RGB bytes, top row first. PNG
+// has no empty images, so a zero width or height is rejected.
+pub fn write_rgb(path: &str, width: usize, height: usize, rows: &[u8]) -> io::Result<()> {
+    if width == 0 || height == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "image must be at least 1x1"));
+    }
+    if rows.len() != width * height * 3 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "pixel data does not match image size"));
+    }
+
+    let mut raw = Vec::with_capacity(height * (width * 3 + 1));
+    for row in rows.chunks(width * 3) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&SIGNATURE)?;
+    write_chunk(&mut writer, b"IHDR", &header)?;
+    write_chunk(&mut writer, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(&mut writer, b"IEND", &[])?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn be_u32(bytes: &[u8]) -> u32 {
+        u32::from_be_bytes(bytes[..4].try_into().unwrap())
+    }
+
+    // Reads back a file write_rgb made, checking every field it writes on
+    // the way, and returns the size and the pixel rows
+    fn read_rgb(bytes: &[u8]) -> (usize, usize, Vec<u8>) {
+        assert_eq!(bytes[..8], SIGNATURE);
+        let mut chunks = Vec::new();
+        let mut rest = &bytes[8..];
+        while !rest.is_empty() {
+            let len = be_u32(rest) as usize;
+            let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+            assert_eq!(be_u32(&rest[8 + len..]), crc32(&rest[4..8 + len]), "bad CRC on {:?}", kind);
+            chunks.push((kind.to_vec(), data.to_vec()));
+            rest = &rest[12 + len..];
+        }
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| kind.as_slice()).collect();
+        assert_eq!(kinds, [b"IHDR".as_slice(), b"IDAT", b"IEND"]);
+
+        let header = &chunks[0].1;
+        let (width, height) = (be_u32(header) as usize, be_u32(&header[4..]) as usize);
+        assert_eq!(header[8..], [8, 2, 0, 0, 0]);
+
+        let zlib = &chunks[1].1;
+        assert_eq!(zlib[..2], [0x78, 0x01]);
+        let mut raw = Vec::new();
+        let mut at = 2;
+        loop {
+            let is_last = zlib[at] == 1;
+            let len = u16::from_le_bytes([zlib[at + 1], zlib[at + 2]]);
+            assert_eq!(u16::from_le_bytes([zlib[at + 3], zlib[at + 4]]), !len);
+            assert!(is_last || len as usize == MAX_STORED_BLOCK);
+            raw.extend_from_slice(&zlib[at + 5..at + 5 + len as usize]);
+            at += 5 + len as usize;
+            if is_last {
+                break;
+            }
+        }
+        assert_eq!(be_u32(&zlib[at..]), adler32(&raw));
+        assert_eq!(at + 4, zlib.len());
+
+        let rows = raw.chunks(width * 3 + 1)
+            .flat_map(|row| {
+                assert_eq!(row[0], 0, "rows are written unfiltered");
+                row[1..].to_vec()
+            })
+            .collect();
+        (width, height, rows)
+    }
+
+    fn round_trip(name: &str, width: usize, height: usize) {
+        let rows: Vec<u8> = (0..width * height * 3).map(|i| (i * 7 % 251) as u8).collect();
+        let path = std::env::temp_dir().join(name);
+        write_rgb(path.to_str().unwrap(), width, height, &rows).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read_rgb(&bytes), (width, height, rows));
+    }
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn small_image_round_trips() {
+        round_trip("sr_02_line_small.png", 3, 2);
+    }
+
+    #[test]
+    fn large_image_splits_into_stored_blocks() {
+        // 120 rows of 601 bytes is 72120 raw bytes, past one stored block
+        round_trip("sr_02_line_large.png", 200, 120);
+    }
+
+    #[test]
+    fn zero_width_is_invalid_input() {
+        let path = std::env::temp_dir().join("sr_02_line_zero_width.png");
+        let error = write_rgb(path.to_str().unwrap(), 0, 4, &[]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+}
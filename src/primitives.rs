@@ -0,0 +1,156 @@
+use nalgebra_glm::{Vec2, Vec3};
+use std::f32::consts::PI;
+use crate::vertex::Vertex;
+
+// Flat annulus in the XZ plane, as a triangle list like Obj::get_vertex_array.
+// Both faces are emitted so it survives backface culling from either side.
+// UVs: u runs radially from the inner (0) to the outer (1) edge, v goes once
+// around the ring.
+pub fn ring(inner_radius: f32, outer_radius: f32, segments: usize) -> Vec<Vertex> {
+    let segments = segments.max(3);
+    let mut vertices = Vec::with_capacity(segments * 12);
+    let up = Vec3::new(0.0, 1.0, 0.0);
+
+    for i in 0..segments {
+        let v0 = i as f32 / segments as f32;
+        let v1 = (i + 1) as f32 / segments as f32;
+        let (sin0, cos0) = (v0 * 2.0 * PI).sin_cos();
+        let (sin1, cos1) = (v1 * 2.0 * PI).sin_cos();
+
+        let inner0 = (Vec3::new(inner_radius * cos0, 0.0, inner_radius * sin0), Vec2::new(0.0, v0));
+        let outer0 = (Vec3::new(outer_radius * cos0, 0.0, outer_radius * sin0), Vec2::new(1.0, v0));
+        let inner1 = (Vec3::new(inner_radius * cos1, 0.0, inner_radius * sin1), Vec2::new(0.0, v1));
+        let outer1 = (Vec3::new(outer_radius * cos1, 0.0, outer_radius * sin1), Vec2::new(1.0, v1));
+
+        for (a, b, c) in [(inner0, outer1, outer0), (inner0, inner1, outer1)] {
+            vertices.push(Vertex::new(a.0, up, a.1));
+            vertices.push(Vertex::new(b.0, up, b.1));
+            vertices.push(Vertex::new(c.0, up, c.1));
+
+            vertices.push(Vertex::new(a.0, -up, a.1));
+            vertices.push(Vertex::new(c.0, -up, c.1));
+            vertices.push(Vertex::new(b.0, -up, b.1));
+        }
+    }
+
+    vertices
+}
+
+// Unit sphere as a triangle list like Obj::get_vertex_array, so it can stand
+// in for the OBJ model. `rings` splits it from pole to pole and `sectors`
+// around the Y axis; the pole rows use one triangle per sector instead of a
+// degenerate quad, giving 6 * sectors * (rings - 1) vertices. Faces wind
+// counter-clockwise seen from outside, normals point outwards, u goes once
+// around and v runs from the north pole (0) to the south pole (1).
+pub fn uv_sphere(rings: usize, sectors: usize) -> Vec<Vertex> {
+    let rings = rings.max(2);
+    let sectors = sectors.max(3);
+    let mut vertices = Vec::with_capacity(6 * sectors * (rings - 1));
+
+    let point = |ring: usize, sector: usize| {
+        let u = sector as f32 / sectors as f32;
+        let v = ring as f32 / rings as f32;
+        let (sin_theta, cos_theta) = (v * PI).sin_cos();
+        let (sin_phi, cos_phi) = (u * 2.0 * PI).sin_cos();
+        let position = Vec3::new(sin_theta * cos_phi, cos_theta, sin_theta * sin_phi);
+        Vertex::new(position, position, Vec2::new(u, v))
+    };
+
+    for ring in 0..rings {
+        for sector in 0..sectors {
+            let top_left = point(ring, sector);
+            let bottom_left = point(ring + 1, sector);
+            let bottom_right = point(ring + 1, sector + 1);
+            let top_right = point(ring, sector + 1);
+
+            if ring != 0 {
+                vertices.extend([top_left.clone(), top_right, bottom_right.clone()]);
+            }
+            if ring != rings - 1 {
+                vertices.extend([top_left, bottom_right, bottom_left]);
+            }
+        }
+    }
+
+    vertices
+}
+
+// Unit sphere made by splitting each face of an icosahedron into four,
+// `subdivisions` times, and pushing the new corners out onto the sphere:
+// 20 * 4^subdivisions triangles of nearly equal size, without the crowded
+// poles of uv_sphere. Same layout as uv_sphere otherwise: a triangle list
+// wound counter-clockwise from outside, outward normals, and u, v from the
+// direction (u once around the Y axis, v from the north pole to the south).
+pub fn icosphere(subdivisions: usize) -> Vec<Vertex> {
+    let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+    let corners = [
+        Vec3::new(-1.0, t, 0.0), Vec3::new(1.0, t, 0.0), Vec3::new(-1.0, -t, 0.0), Vec3::new(1.0, -t, 0.0),
+        Vec3::new(0.0, -1.0, t), Vec3::new(0.0, 1.0, t), Vec3::new(0.0, -1.0, -t), Vec3::new(0.0, 1.0, -t),
+        Vec3::new(t, 0.0, -1.0), Vec3::new(t, 0.0, 1.0), Vec3::new(-t, 0.0, -1.0), Vec3::new(-t, 0.0, 1.0),
+    ].map(|corner| corner.normalize());
+    let faces = [
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    let mut triangles: Vec<[Vec3; 3]> = faces.iter()
+        .map(|&[a, b, c]| [corners[a], corners[b], corners[c]])
+        .collect();
+    for _ in 0..subdivisions {
+        triangles = triangles.iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = ((a + b).normalize(), (b + c).normalize(), (c + a).normalize());
+                [[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    triangles.iter()
+        .flatten()
+        .map(|&position| {
+            let u = (position.z.atan2(position.x) / (2.0 * PI)).rem_euclid(1.0);
+            let v = position.y.clamp(-1.0, 1.0).acos() / PI;
+            Vertex::new(position, position, Vec2::new(u, v))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uv_sphere_vertex_count() {
+        // Two triangles per sector on every row but the two pole rows, which
+        // have one
+        for (rings, sectors) in [(2, 3), (6, 20), (16, 32)] {
+            assert_eq!(uv_sphere(rings, sectors).len(), 6 * sectors * (rings - 1));
+        }
+    }
+
+    #[test]
+    fn uv_sphere_vertices_lie_on_the_unit_sphere() {
+        for vertex in uv_sphere(8, 12) {
+            assert!((vertex.position.magnitude() - 1.0).abs() < 1e-5);
+            assert_eq!(vertex.normal, vertex.position);
+        }
+    }
+
+    #[test]
+    fn icosphere_vertex_count() {
+        // 20 faces, each split into four per subdivision, three corners each
+        for subdivisions in 0..4 {
+            assert_eq!(icosphere(subdivisions).len(), 60 * 4usize.pow(subdivisions as u32));
+        }
+    }
+
+    #[test]
+    fn icosphere_vertices_lie_on_the_unit_sphere() {
+        for vertex in icosphere(2) {
+            assert!((vertex.position.magnitude() - 1.0).abs() < 1e-5);
+            assert_eq!(vertex.normal, vertex.position);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs;
+use std::io;
+use nalgebra_glm::Vec3;
+use crate::camera::CameraView;
+
+// Everything needed to redraw one frame of a recorded session: the clocks
+// driving animation and orbits, and where the camera was
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    pub seconds: f32,
+    pub orbit_seconds: f32,
+    pub view: CameraView,
+}
+
+// File layout, all little-endian: the MAGIC bytes, a u32 format VERSION, a
+// u32 frame count, then per frame 11 f32s: seconds, orbit seconds, and eye,
+// center and up as x, y, z. Bump VERSION whenever the frame layout changes.
+const MAGIC: &[u8; 8] = b"SRCAMREC";
+const VERSION: u32 = 1;
+const FLOATS_PER_FRAME: usize = 11;
+
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    pub frames: Vec<Keyframe>,
+}
+
+impl Recording {
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut data = Vec::with_capacity(16 + self.frames.len() * FLOATS_PER_FRAME * 4);
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            let view = frame.view;
+            let floats = [
+                frame.seconds, frame.orbit_seconds,
+                view.eye.x, view.eye.y, view.eye.z,
+                view.center.x, view.center.y, view.center.z,
+                view.up.x, view.up.y, view.up.z,
+            ];
+            for value in floats {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        fs::write(path, data)
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, message));
+
+        if data.len() < 16 || &data[..8] != MAGIC {
+            return Err(invalid(String::from("not a camera recording")));
+        }
+        let read_u32 = |offset: usize| u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+        let version = read_u32(8);
+        if version != VERSION {
+            return Err(invalid(format!("unsupported recording version {} (expected {})", version, VERSION)));
+        }
+        let count = read_u32(12) as usize;
+        let body = &data[16..];
+        if body.len() != count * FLOATS_PER_FRAME * 4 {
+            return Err(invalid(format!("expected {} frames, file is truncated or padded", count)));
+        }
+
+        let frames = body
+            .chunks_exact(FLOATS_PER_FRAME * 4)
+            .map(|chunk| {
+                let f = |index: usize| f32::from_le_bytes([chunk[index * 4], chunk[index * 4 + 1], chunk[index * 4 + 2], chunk[index * 4 + 3]]);
+                Keyframe {
+                    seconds: f(0),
+                    orbit_seconds: f(1),
+                    view: CameraView {
+                        eye: Vec3::new(f(2), f(3), f(4)),
+                        center: Vec3::new(f(5), f(6), f(7)),
+                        up: Vec3::new(f(8), f(9), f(10)),
+                    },
+                }
+            })
+            .collect();
+        Ok(Recording { frames })
+    }
+}
@@ -0,0 +1,171 @@
+use std::fs;
+use std::io;
+use nalgebra_glm::Vec3;
+use serde::{Deserialize, Deserializer};
+use crate::color::Color;
+use crate::material::Material;
+use crate::shaders::SHADER_COUNT;
+
+// One body of the scene, from a scene file or the built-in table
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PlanetDesc {
+    // OBJ path; None uses the default sphere
+    #[serde(default)]
+    pub model: Option<String>,
+    // Orbit position at phase 0, relative to the parent
+    #[serde(deserialize_with = "vec3")]
+    pub position: Vec3,
+    pub scale: f32,
+    #[serde(default)]
+    pub orbit_speed: f32,
+    #[serde(default)]
+    pub phase: f32,
+    pub shader: usize,
+    #[serde(default = "default_material", deserialize_with = "material")]
+    pub material: Material,
+    // Night-side ambient tint
+    #[serde(default = "Color::black", deserialize_with = "color")]
+    pub ambient: Color,
+    // Self-rotation speed around the local Y axis
+    #[serde(default)]
+    pub spin: f32,
+    // Seeds the body's noise generators
+    #[serde(default)]
+    pub seed: i32,
+    // Index of the body it orbits; the origin when None
+    #[serde(default)]
+    pub parent: Option<usize>,
+}
+
+impl PlanetDesc {
+    // A matte white sphere sitting still at `position`, with every optional
+    // field at the same default a scene file uses
+    pub fn new(position: Vec3, scale: f32, shader: usize) -> Self {
+        PlanetDesc {
+            model: None,
+            position,
+            scale,
+            orbit_speed: 0.0,
+            phase: 0.0,
+            shader,
+            material: default_material(),
+            ambient: Color::black(),
+            spin: 0.0,
+            seed: 0,
+            parent: None,
+        }
+    }
+}
+
+// Reads a JSON scene file: an object with a "bodies" array. Each body needs
+// "position" ([x, y, z]), "scale" and "shader"; everything else is optional:
+//
+//   "model": "path.obj", "orbit_speed": 0, "phase": 0, "spin": 0,
+//   "seed": 0, "parent": null, "ambient": [0, 0, 0],
+//   "material": { "base_color": [255, 255, 255], "specular_color": [0, 0, 0],
+//                 "shininess": 1, "roughness": 1, "emissive": false,
+//                 "emission": 1 }
+//
+// Colors are 0-255 per channel. A parent must come before its children.
+// Shader 14 meant the black-and-white fallback before the atmosphere shell
+// took that index, so older scenes asking for it still get the fallback.
+pub fn load(path: &str) -> io::Result<Vec<PlanetDesc>> {
+    let text = fs::read_to_string(path)?;
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, message));
+
+    let mut scene: SceneFile = serde_json::from_str(&text).map_err(|error| invalid(error.to_string()))?;
+    for (index, planet) in scene.bodies.iter_mut().enumerate() {
+        if planet.parent.is_some_and(|parent| parent >= index) {
+            return Err(invalid(format!("body {}: parent must be an earlier body", index)));
+        }
+        if planet.shader == crate::ATMOSPHERE_SHADER_INDEX {
+            eprintln!(
+                "{}: body {}: shader {} is reserved for atmospheres, using the black-and-white fallback",
+                path, index, planet.shader,
+            );
+            planet.shader = SHADER_COUNT - 1;
+        }
+    }
+    Ok(scene.bodies)
+}
+
+#[derive(Deserialize)]
+struct SceneFile {
+    bodies: Vec<PlanetDesc>,
+}
+
+// The "material" object; every key falls back to the matte white default
+#[derive(Deserialize)]
+#[serde(default)]
+struct MaterialDesc {
+    #[serde(deserialize_with = "color")]
+    base_color: Color,
+    #[serde(deserialize_with = "color")]
+    specular_color: Color,
+    shininess: f32,
+    roughness: f32,
+    emissive: bool,
+    emission: f32,
+}
+
+impl Default for MaterialDesc {
+    fn default() -> Self {
+        let material = default_material();
+        MaterialDesc {
+            base_color: material.base_color,
+            specular_color: material.specular_color,
+            shininess: material.shininess,
+            roughness: material.roughness,
+            emissive: material.emissive,
+            emission: material.emission,
+        }
+    }
+}
+
+fn default_material() -> Material {
+    Material::matte(Color::new(255, 255, 255))
+}
+
+fn material<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Material, D::Error> {
+    let desc = MaterialDesc::deserialize(deserializer)?;
+    Ok(Material::new(desc.base_color, desc.specular_color, desc.shininess, desc.roughness, desc.emissive)
+        .with_emission(desc.emission))
+}
+
+fn vec3<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec3, D::Error> {
+    let [x, y, z] = <[f32; 3]>::deserialize(deserializer)?;
+    Ok(Vec3::new(x, y, z))
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let channels = vec3(deserializer)?;
+    let channel = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+    Ok(Color::new(channel(channels.x), channel(channels.y), channel(channels.z)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solar_system_scene_matches_built_in_bodies() {
+        let bodies = load("assets/scenes/solar_system.json").unwrap();
+        assert_eq!(bodies, crate::built_in_bodies());
+    }
+
+    #[test]
+    fn omitted_fields_use_defaults() {
+        let scene: SceneFile = serde_json::from_str(r#"{ "bodies": [{ "position": [1, 2, 3], "scale": 0.5, "shader": 4 }] }"#).unwrap();
+        assert_eq!(scene.bodies, vec![PlanetDesc::new(Vec3::new(1.0, 2.0, 3.0), 0.5, 4)]);
+    }
+
+    #[test]
+    fn old_fallback_shader_index_still_means_black_and_white() {
+        let path = std::env::temp_dir().join("sr_02_line_old_fallback_scene.json");
+        fs::write(&path, r#"{ "bodies": [{ "position": [0, 0, 0], "scale": 1, "shader": 14 }] }"#).unwrap();
+        let bodies = load(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(bodies[0].shader, SHADER_COUNT - 1);
+    }
+}
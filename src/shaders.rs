@@ -1,12 +1,12 @@
-use nalgebra_glm::{Vec3, Vec4, Mat3, mat4_to_mat3};
+use nalgebra_glm::{Vec2, Vec3, Vec4, Mat3, Mat4, mat4_to_mat3};
 use crate::vertex::Vertex;
 use crate::Uniforms;
 use crate::fragment::Fragment;
 use crate::color::Color;
-use std::f32::consts::PI;
 use rand::Rng;
 use rand::SeedableRng;
 use rand::rngs::StdRng;
+use std::f32::consts::PI;
 
 pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms) -> Vertex {
     let position = Vec4::new(
@@ -16,20 +16,10 @@ pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms) -> Vertex {
         1.0,
     );
 
-    let transformed = uniforms.projection_matrix
+    let world_position = uniforms.model_matrix * position;
+    let clip_position = uniforms.projection_matrix
         * uniforms.view_matrix
-        * uniforms.model_matrix
-        * position;
-
-    let w = transformed.w;
-    let transformed_position = Vec4::new(
-        transformed.x / w,
-        transformed.y / w,
-        transformed.z / w,
-        1.0,
-    );
-
-    let screen_position = uniforms.viewport_matrix * transformed_position;
+        * world_position;
 
     let model_mat3 = mat4_to_mat3(&uniforms.model_matrix);
     let normal_matrix = model_mat3.transpose().try_inverse().unwrap_or(Mat3::identity());
@@ -41,13 +31,45 @@ pub fn vertex_shader(vertex: &Vertex, uniforms: &Uniforms) -> Vertex {
         normal: vertex.normal,
         tex_coords: vertex.tex_coords,
         color: vertex.color,
-        transformed_position: Vec3::new(screen_position.x, screen_position.y, screen_position.z),
+        clip_position,
+        transformed_position: vertex.transformed_position,
         transformed_normal,
+        world_position: world_position.xyz(),
     }
 }
 
-pub fn fragment_shader(fragment: &Fragment, uniforms: &Uniforms, sphere_index: usize) -> Color {
-    match sphere_index {
+// Perspective divide plus viewport transform, run on clipped vertices
+pub fn screen_mapping(vertex: &mut Vertex, uniforms: &Uniforms) {
+    let clip = vertex.clip_position;
+    // Clipping keeps w >= 0, but a vertex sitting exactly on the eye still has w == 0
+    let w = if clip.w.abs() > f32::EPSILON { clip.w } else { f32::EPSILON };
+    let ndc_position = Vec4::new(
+        clip.x / w,
+        clip.y / w,
+        clip.z / w,
+        1.0,
+    );
+
+    let screen_position = uniforms.viewport_matrix * ndc_position;
+    vertex.transformed_position = Vec3::new(screen_position.x, screen_position.y, screen_position.z);
+}
+
+// Shader indices fragment_shader tells apart: 0 to 14 by name, and 15 (or
+// anything higher) for the black-and-white fallback
+pub const SHADER_COUNT: usize = 16;
+
+pub fn fragment_shader(fragment: &Fragment, uniforms: &Uniforms, shader_index: usize) -> Color {
+    if uniforms.debug_depth {
+        return depth_shader(fragment, uniforms);
+    }
+
+    // Per-pixel diffuse term from the interpolated normal
+    let fragment = &Fragment {
+        intensity: diffuse_intensity(&fragment.normal, &fragment.world_position, uniforms),
+        ..*fragment
+    };
+
+    let color = match shader_index {
         
         0 => solar_shader(fragment, uniforms),
         1 => dalmata_shader(fragment, uniforms),
@@ -57,12 +79,295 @@ pub fn fragment_shader(fragment: &Fragment, uniforms: &Uniforms, sphere_index: u
         5 => rocky_planet_shader(fragment, uniforms),
         6 => earth_shader(fragment, uniforms),
         7 => gaseous_planet_shader(fragment, uniforms),
+        8 => metal_shader(fragment, uniforms),
+        9 => ring_shader(fragment, uniforms),
+        10 => ice_shader(fragment, uniforms),
+        11 => textured_shader(fragment, uniforms),
+        12 => water_shader(fragment, uniforms),
+        13 => vertex_color_shader(fragment, uniforms),
+        14 => atmosphere_shader(fragment, uniforms),
         _ => black_and_white(fragment, uniforms),
+    };
+
+    apply_fog(color, fragment, uniforms)
+}
+
+// Exponential distance fog: the share of the surface color left is
+// exp(-density * distance), so nothing changes at the eye and everything is
+// fog-colored well before the far plane. A density of 0 turns it off.
+fn apply_fog(color: Color, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+    fog_blend(color, &uniforms.fog_color, uniforms.fog_density, linear_depth(fragment.depth, uniforms))
+}
+
+// The blend `apply_fog` does, for anything else drawn into the fog, given
+// its distance from the eye along the view axis
+pub fn fog_blend(color: Color, fog_color: &Vec3, density: f32, distance: f32) -> Color {
+    if density <= 0.0 {
+        return color;
+    }
+    let visibility = (-density * distance).exp();
+    Color::from_rgb(fog_color).lerp(&color, visibility)
+}
+
+// The depth view fades to black this far past the near plane even when the
+// far plane is further out. At the default far of 1000, mapping all the way
+// to it would leave the whole system white.
+const DEBUG_DEPTH_RANGE: f32 = 15.0;
+
+// Distance along the view axis for a fragment's NDC depth in [-1, 1],
+// undoing the projection with the near/far uniforms: hyperbolic for
+// perspective, linear for orthographic
+fn linear_depth(depth: f32, uniforms: &Uniforms) -> f32 {
+    view_distance(depth, uniforms.near, uniforms.far, &uniforms.projection_matrix)
+}
+
+// `linear_depth` for callers without uniforms
+pub fn view_distance(depth: f32, near: f32, far: f32, projection_matrix: &Mat4) -> f32 {
+    // Perspective matrices have a 0 in the bottom-right corner
+    if projection_matrix[(3, 3)] == 0.0 {
+        2.0 * near * far / (far + near - depth * (far - near))
+    } else {
+        (depth * (far - near) + far + near) * 0.5
     }
 }
 
+// Grayscale by linear view distance: white at the near plane, black at the
+// far plane or DEBUG_DEPTH_RANGE past near, whichever is closer
+fn depth_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
+    let far = uniforms.far.min(uniforms.near + DEBUG_DEPTH_RANGE);
+    let distance = linear_depth(fragment.depth, uniforms);
+    let t = ((distance - uniforms.near) / (far - uniforms.near)).clamp(0.0, 1.0);
+    let value = ((1.0 - t) * 255.0).round() as u8;
+    Color::new(value, value, value)
+}
+
+// Opacity of a shaded fragment. Only the ring and the atmosphere are
+// translucent; everything else keeps the fragment's own alpha.
+pub fn fragment_alpha(fragment: &Fragment, uniforms: &Uniforms, shader_index: usize) -> f32 {
+    match shader_index {
+        9 => ring_alpha(fragment, uniforms) * fragment.alpha,
+        14 => atmosphere_alpha(fragment, uniforms) * fragment.alpha,
+        _ => fragment.alpha,
+    }
+}
+
+// Direction towards the main light from a world-space position: the sun when
+// sun lighting is on, otherwise the fixed direction set with I/J/K/L
+fn light_direction_at(position: &Vec3, uniforms: &Uniforms) -> Vec3 {
+    if !uniforms.sun_lighting {
+        return uniforms.light_dir;
+    }
+    (uniforms.sun_pos - position).try_normalize(f32::EPSILON).unwrap_or(uniforms.light_dir)
+}
+
+// Diffuse light from the point light, falling off with the squared distance.
+// The +1 keeps the attenuation finite for fragments right next to the light.
+fn point_light(fragment: &Fragment, uniforms: &Uniforms) -> Vec3 {
+    let to_light = uniforms.light_pos - fragment.world_position;
+    let distance_squared = to_light.magnitude_squared();
+    let Some(light_dir) = to_light.try_normalize(f32::EPSILON) else {
+        return uniforms.light_color;
+    };
+
+    let diffuse = fragment.normal.dot(&light_dir).max(0.0);
+    uniforms.light_color * (diffuse / (1.0 + distance_squared))
+}
+
+// Diffuse shading with a floor: `color * (ambient + (1 - ambient) * intensity)`,
+// so the side facing away from the light keeps `ambient` of its albedo. The
+// body's ambient color tints that floor on top (e.g. the earth's night side).
+fn ambient_diffuse(color: Color, intensity: f32, uniforms: &Uniforms) -> Color {
+    let unlit = color * uniforms.ambient + color.blend_multiply(&uniforms.ambient_color);
+    unlit.lerp(&color, intensity)
+}
+
+// Lambert term for a world-space normal and position
+fn diffuse_intensity(normal: &Vec3, world_position: &Vec3, uniforms: &Uniforms) -> f32 {
+    normal.dot(&light_direction_at(world_position, uniforms)).max(0.0)
+}
+
+// Emissive bodies give off their own light, so they skip the diffuse term.
+// Lit bodies go through `ambient_diffuse`, and the material's specular color
+// is added on top as a Blinn-Phong highlight.
+fn apply_lighting(color: Color, fragment: &Fragment, uniforms: &Uniforms) -> Color {
+    let material = &uniforms.material;
+    if material.emissive {
+        return color;
+    }
+
+    // `fragment_shader` has already filled in the diffuse term as `intensity`
+    let view_dir = (uniforms.camera_position - fragment.world_position).normalize();
+    let light_dir = light_direction_at(&fragment.world_position, uniforms);
+    let (_, specular) = blinn_phong(fragment.normal, light_dir, view_dir, material.shininess);
+    let specular = (specular * (1.0 - material.roughness)).clamp(0.0, 1.0);
+
+    let shadow = sun_visibility(&fragment.world_position, uniforms);
+    let specular = specular * shadow;
+
+    let point = point_light(fragment, uniforms);
+    let to_channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0) as u8;
+    let point_tint = Color::new(to_channel(point.x), to_channel(point.y), to_channel(point.z));
+
+    ambient_diffuse(color, fragment.intensity * shadow, uniforms)
+        + color.blend_multiply(&point_tint)
+        + material.specular_color.scale(specular)
+}
+
+// How much of the sun a world-space point can see past the other bodies, from
+// 1 (fully lit) to 0 (umbra). Each caster is tested against the segment to
+// the sun's center. Seen from the point, the sun's disk spreads to
+// sun_radius * t at a fraction t of the way there, which sets the penumbra
+// width around the caster's edge, so shadows soften as the caster gets
+// further from the surface.
+fn sun_visibility(position: &Vec3, uniforms: &Uniforms) -> f32 {
+    if !uniforms.sun_lighting || uniforms.shadow_casters.is_empty() {
+        return 1.0;
+    }
+    // The first caster is the sun itself
+    let sun_radius = uniforms.shadow_casters[0].1;
+    let to_sun = uniforms.sun_pos - position;
+    let length_squared = to_sun.magnitude_squared();
+    if length_squared <= f32::EPSILON {
+        return 1.0;
+    }
+
+    let mut visibility: f32 = 1.0;
+    for (index, (center, radius)) in uniforms.shadow_casters.iter().enumerate().skip(1) {
+        if index == uniforms.body_index {
+            continue;
+        }
+        let t = (center - position).dot(&to_sun) / length_squared;
+        if t <= 0.0 || t >= 1.0 {
+            continue;
+        }
+        let closest = position + to_sun * t;
+        let miss_distance = (center - closest).magnitude();
+        let penumbra = (sun_radius * t).max(0.01);
+        let coverage = ((miss_distance - (radius - penumbra)) / (2.0 * penumbra)).clamp(0.0, 1.0);
+        visibility = visibility.min(coverage * coverage * (3.0 - 2.0 * coverage));
+    }
+    visibility
+}
+
+// Fresnel-style rim term: 0 where the surface faces the camera, rising to 1
+// at grazing angles along the silhouette. Higher powers give a thinner rim.
+fn rim_intensity(fragment: &Fragment, uniforms: &Uniforms, power: f32) -> f32 {
+    let view_dir = (uniforms.camera_position - fragment.world_position).normalize();
+    let normal = fragment.normal.normalize();
+    (1.0 - normal.dot(&view_dir).clamp(0.0, 1.0)).powf(power)
+}
+
+// Lambert diffuse and Blinn-Phong specular (from the half vector between
+// the light and view directions), both in [0, 1]. All directions point away
+// from the surface.
+fn blinn_phong(normal: Vec3, light_dir: Vec3, view_dir: Vec3, shininess: f32) -> (f32, f32) {
+    let normal = normal.normalize();
+    let diffuse = normal.dot(&light_dir).clamp(0.0, 1.0);
+    if diffuse <= 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let half_dir = (light_dir + view_dir).normalize();
+    let specular = normal.dot(&half_dir).max(0.0).powf(shininess).clamp(0.0, 1.0);
+
+    (diffuse, specular)
+}
+
+// Live adjustments on top of each shader's own noise constants, so a look
+// can be tuned without recompiling. `zoom` and `speed` multiply the shader's
+// zoom and animation speed; `offset` is added to its ox and oy, in whatever
+// units that shader uses them. The default leaves every shader unchanged.
+// Dalmata, cloud, cellular, lava and rocky honor all three; gaseous, solar
+// and earth have no offsets and only honor zoom and speed. The rest ignore it.
+#[derive(Debug, Clone, Copy)]
+pub struct NoiseParams {
+    pub zoom: f32,
+    pub offset: Vec2,
+    pub speed: f32,
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        NoiseParams { zoom: 1.0, offset: Vec2::zeros(), speed: 1.0 }
+    }
+}
+
+const BAYER_4X4: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+// Object-space step used to take finite differences of a height function
+const BUMP_SAMPLE_STEP: f32 = 0.01;
+const ROCKY_BUMP_STRENGTH: f32 = 0.15;
+// Terrain height to color, lowland to plains to mountains. The stops sit
+// around the old hard band edges at 0.2 and 0.5.
+const ROCKY_GRADIENT: [(f32, Color); 3] = [
+    (0.1, Color::new(222, 184, 135)),
+    (0.35, Color::new(205, 133, 63)),
+    (0.6, Color::new(139, 69, 19)),
+];
+// Remapped lava noise above this turns white-hot
+const LAVA_CRACK_THRESHOLD: f32 = 0.65;
+// Gas giant domain warp: sample scale of the warping noise and how far, in
+// object-space units, it can push a sample
+const GAS_WARP_ZOOM: f32 = 400.0;
+const GAS_WARP_STRENGTH: f32 = 0.04;
+// Great red spot on the gas giant, in object space (the sphere has radius
+// 0.5): center, horizontal and vertical semi-axes, and the width of its soft
+// rim as a fraction of the size
+const GAS_SPOT_CENTER: Vec3 = Vec3::new(0.2, 0.1, 0.445);
+const GAS_SPOT_SIZE: Vec2 = Vec2::new(0.16, 0.08);
+const GAS_SPOT_EDGE: f32 = 0.3;
+// Atmosphere shell: its radius over the planet's, how much light it absorbs
+// per planet radius of path, and its color when fully lit
+pub const ATMOSPHERE_SCALE: f32 = 1.08;
+const ATMOSPHERE_DENSITY: f32 = 3.0;
+const ATMOSPHERE_COLOR: Color = Color::new(110, 170, 255);
+
+// Normal mapping without a texture: `height` is sampled at the fragment and
+// one step along each tangent direction, and the normal is tilted against
+// that gradient. Works in object space so the bumps stay attached to the
+// surface as the body spins. Returns a copy of the fragment with the new
+// normal and a diffuse intensity recomputed from it.
+fn bump_fragment(fragment: &Fragment, uniforms: &Uniforms, strength: f32, height: impl Fn(&Vec3) -> f32) -> Fragment {
+    // The normal matrix is the inverse transpose of this, so its transpose
+    // takes world normals back to object space (up to scale)
+    let model_mat3 = mat4_to_mat3(&uniforms.model_matrix);
+    let normal = (model_mat3.transpose() * fragment.normal).normalize();
+
+    let helper = if normal.y.abs() < 0.99 { Vec3::y() } else { Vec3::x() };
+    let tangent = helper.cross(&normal).normalize();
+    let bitangent = normal.cross(&tangent);
+
+    let position = fragment.object_position;
+    let base = height(&position);
+    let slope_t = (height(&(position + tangent * BUMP_SAMPLE_STEP)) - base) / BUMP_SAMPLE_STEP;
+    let slope_b = (height(&(position + bitangent * BUMP_SAMPLE_STEP)) - base) / BUMP_SAMPLE_STEP;
+    let bumped = (normal - (tangent * slope_t + bitangent * slope_b) * strength).normalize();
+
+    let normal_matrix = model_mat3.transpose().try_inverse().unwrap_or(Mat3::identity());
+    let mut result = *fragment;
+    result.normal = (normal_matrix * bumped).normalize();
+    result.intensity = diffuse_intensity(&result.normal, &fragment.world_position, uniforms);
+    result
+}
+
+// Ordered dithering: nudges a value by a screen-position dependent offset in
+// [-0.5, 0.5) * dither_strength, so values close to a band threshold land on
+// either side in a fixed pattern instead of forming a hard edge.
+fn dither(value: f32, fragment: &Fragment, uniforms: &Uniforms) -> f32 {
+    let x = fragment.position.x as usize % 4;
+    let y = fragment.position.y as usize % 4;
+    let offset = (BAYER_4X4[y][x] + 0.5) / 16.0 - 0.5;
+
+    value + offset * uniforms.dither_strength
+}
+
 fn black_and_white(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-    let seed = uniforms.time as f32 * fragment.vertex_position.y * fragment.vertex_position.x;
+    let seed = uniforms.animation_time() * fragment.object_position.y * fragment.object_position.x;
 
     let mut rng = StdRng::seed_from_u64(seed.abs() as u64);
 
@@ -74,18 +379,18 @@ fn black_and_white(fragment: &Fragment, uniforms: &Uniforms) -> Color {
         Color::new(255, 255, 255)
     };
 
-    black_or_white * fragment.intensity
+    apply_lighting(black_or_white, fragment, uniforms)
 }
   
 fn dalmata_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-  let zoom = 100.0;
-  let ox = 0.0;
-  let oy = 0.0;
-  let speed = 0.5; 
-  let time = uniforms.time as f32 * speed;
+  let zoom = 100.0 * uniforms.noise_params.zoom;
+  let ox = 0.0 + uniforms.noise_params.offset.x;
+  let oy = 0.0 + uniforms.noise_params.offset.y;
+  let speed = 0.5 * uniforms.noise_params.speed;
+  let time = uniforms.animation_time() * speed;
 
-  let x = fragment.vertex_position.x;
-  let y = fragment.vertex_position.y;
+  let x = fragment.object_position.x;
+  let y = fragment.object_position.y;
 
   let noise_value = uniforms.noise.get_noise_2d(
       (x + ox) * zoom + time,
@@ -102,17 +407,17 @@ fn dalmata_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
       base_color
   };
 
-  noise_color * fragment.intensity
+  apply_lighting(noise_color, fragment, uniforms)
 }
 
   
 fn cloud_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-    let zoom = 100.0;  
-    let ox = 100.0; 
-    let oy = 100.0;
-    let x = fragment.vertex_position.x;
-    let y = fragment.vertex_position.y;
-    let t = uniforms.time as f32 * 0.5;
+    let zoom = 100.0 * uniforms.noise_params.zoom;
+    let ox = 100.0 + uniforms.noise_params.offset.x;
+    let oy = 100.0 + uniforms.noise_params.offset.y;
+    let x = fragment.object_position.x;
+    let y = fragment.object_position.y;
+    let t = uniforms.animation_time() * 0.5 * uniforms.noise_params.speed;
   
     let noise_value = uniforms.noise.get_noise_2d(x * zoom + ox + t, y * zoom + oy);
   
@@ -128,18 +433,18 @@ fn cloud_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
       sky_color
     };
   
-    noise_color * fragment.intensity
+    apply_lighting(noise_color, fragment, uniforms)
 }
   
 fn cellular_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-  let zoom = 30.0; 
-  let ox = 50.0;   
-  let oy = 50.0;   
-  let speed = 0.1; 
-  let time = uniforms.time as f32 * speed;
+  let zoom = 30.0 * uniforms.noise_params.zoom;
+  let ox = 50.0 + uniforms.noise_params.offset.x;
+  let oy = 50.0 + uniforms.noise_params.offset.y;
+  let speed = 0.1 * uniforms.noise_params.speed;
+  let time = uniforms.animation_time() * speed;
 
-  let x = fragment.vertex_position.x;
-  let y = fragment.vertex_position.y;
+  let x = fragment.object_position.x;
+  let y = fragment.object_position.y;
 
   
   let cell_noise_value = uniforms.noise.get_noise_2d(
@@ -165,7 +470,7 @@ fn cellular_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
   };
 
   
-  final_color * fragment.intensity
+  apply_lighting(final_color, fragment, uniforms)
 }
 
   
@@ -173,77 +478,83 @@ fn lava_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
   
   let bright_color = Color::new(255, 240, 0); 
   let dark_color = Color::new(130, 20, 0);   
+  let hot_color = Color::new(255, 250, 220);
 
   
-  let position = Vec3::new(
-    fragment.vertex_position.x,
-    fragment.vertex_position.y,
-    fragment.depth
-  );
+  let position = fragment.object_position;
 
   
-  let zoom = 100.0; 
-  let ox = 100.0;   
-  let oy = 100.0;   
-  let t = uniforms.time as f32 * 0.01; 
+  let zoom = 100.0 * uniforms.noise_params.zoom;
+  let ox = 100.0 + uniforms.noise_params.offset.x;
+  let oy = 100.0 + uniforms.noise_params.offset.y;
+  let t = uniforms.animation_time() * 0.01 * uniforms.noise_params.speed;
 
   
-  let noise_value1 = uniforms.noise.get_noise_3d(
+  let noise_value1 = uniforms.fractal_noise.get_noise_3d(
       (position.x + ox + t) * zoom,
       (position.y + oy + t) * zoom,
       position.z * zoom
   );
-  let noise_value2 = uniforms.noise.get_noise_3d(
+  let noise_value2 = uniforms.fractal_noise.get_noise_3d(
       (position.x + ox - t) * zoom,
       (position.y + oy - t) * zoom,
       position.z * zoom
   );
-  let noise_value = (noise_value1 + noise_value2) * 0.5;  
+  let noise_value = lava_heat(noise_value1, noise_value2);
 
-  
-  let color = dark_color.lerp(&bright_color, noise_value);
+  // The hottest spots glow towards white, like cracks in the crust
+  let hot = ((noise_value - LAVA_CRACK_THRESHOLD) / (1.0 - LAVA_CRACK_THRESHOLD)).clamp(0.0, 1.0);
+  let color = dark_color.lerp(&bright_color, noise_value).lerp(&hot_color, hot);
 
-  color * fragment.intensity
+  apply_lighting(color, fragment, uniforms)
 }
 
-fn rocky_planet_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-  let zoom = 50.0; 
-  let ox = 10.0;  
-  let oy = 20.0;
-  let speed = 0.5; 
-  let time = uniforms.time as f32 * speed;
-
-  let x = fragment.vertex_position.x;
-  let y = fragment.vertex_position.y;
+// Averages the two lava noise layers, each roughly -1..1, and remaps the
+// result to 0..1 so the whole range reaches the gradient instead of
+// everything below zero flattening to the dark color
+fn lava_heat(noise_value1: f32, noise_value2: f32) -> f32 {
+  ((noise_value1 + noise_value2) * 0.25 + 0.5).clamp(0.0, 1.0)
+}
 
-  let noise_value = uniforms.noise.get_noise_2d(
-      x * zoom + ox + time,
-      y * zoom + oy + time
+fn rocky_planet_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
+  let zoom = 50.0 * uniforms.noise_params.zoom;
+  let ox = 10.0 + uniforms.noise_params.offset.x;
+  let oy = 20.0 + uniforms.noise_params.offset.y;
+  let speed = 0.5 * uniforms.noise_params.speed;
+  let time = uniforms.animation_time() * speed;
+
+  let terrain_height = |position: &Vec3| uniforms.noise.get_noise_2d(
+      position.x * zoom + ox + time,
+      position.y * zoom + oy + time
   ).abs();
+  let noise_value = terrain_height(&fragment.object_position);
 
-  let mountain_color = Color::new(139, 69, 19); 
-  let plain_color = Color::new(205, 133, 63);  
-  let lowland_color = Color::new(222, 184, 135);   
-
-  let final_color = if noise_value < 0.2 {
-      lowland_color  
-  } else if noise_value < 0.5 {
-      plain_color  
-  } else {
-      mountain_color 
-  };
+  let final_color = Color::gradient(&ROCKY_GRADIENT, noise_value);
 
-  final_color * fragment.intensity
+  let bumped = bump_fragment(fragment, uniforms, ROCKY_BUMP_STRENGTH, terrain_height);
+  apply_lighting(final_color, &bumped, uniforms)
 }
 
+// Domain warping: a slow, low-frequency noise field pushes the point each
+// sample is taken at, so the bands and the storm spot get swirled edges. The
+// warp and the spot live in object space and turn with the planet.
 fn gaseous_planet_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-  let zoom = 0.3; 
-  let speed = 0.1; 
-  let time = uniforms.time as f32 * speed;
+  let zoom = 0.3 * uniforms.noise_params.zoom;
+  let speed = 0.1 * uniforms.noise_params.speed;
+  let time = uniforms.animation_time() * speed;
+
+  let p = fragment.object_position * GAS_WARP_ZOOM;
+  let drift = time * 0.2;
+  let warp = Vec3::new(
+      uniforms.noise.get_noise_3d(p.x + drift, p.y, p.z),
+      uniforms.noise.get_noise_3d(p.x + 31.0, p.y + drift, p.z),
+      uniforms.noise.get_noise_3d(p.x, p.y + 57.0, p.z + drift),
+  );
+  let warped = fragment.object_position + warp * GAS_WARP_STRENGTH;
 
-  let x = fragment.vertex_position.x;
-  let y = fragment.vertex_position.y;
-  let z = fragment.depth;
+  let x = warped.x;
+  let y = warped.y;
+  let z = warped.z;
 
   let noise_value = uniforms.noise.get_noise_3d(
       x * zoom + time,
@@ -251,31 +562,47 @@ fn gaseous_planet_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
       z * zoom
   ).abs();
 
-  let gas_color_1 = Color::new(135, 206, 250); 
-  let gas_color_2 = Color::new(176, 224, 230); 
-  let gas_color_3 = Color::new(255, 228, 196); 
+  let noise_value = dither(noise_value, fragment, uniforms);
 
-  let final_color = if noise_value < 0.4 {
-      gas_color_1
+  // Each band is a hue range (start, width in degrees, saturation, value)
+  // instead of one flat color; the position inside the band picks the hue
+  let (band, t) = if noise_value < 0.4 {
+      ((195.0, 20.0, 0.46, 0.98), noise_value / 0.4)
   } else if noise_value < 0.7 {
-      gas_color_2
+      ((180.0, 15.0, 0.23, 0.9), (noise_value - 0.4) / 0.3)
   } else {
-      gas_color_3
+      ((25.0, 20.0, 0.23, 1.0), ((noise_value - 0.7) / 0.3).min(1.0))
   };
-
-  final_color * fragment.intensity
+  let (hue_start, hue_width, saturation, value) = band;
+
+  // The whole palette swings slowly back and forth around its base hues
+  let hue_drift = (uniforms.animation_time() * 0.002).sin() * 30.0;
+  let band_color = Color::from_hsv(hue_start + hue_width * t + hue_drift, saturation, value);
+
+  // Elliptical storm: distance from the spot center with the horizontal part
+  // measured against the wide axis and the vertical part against the short
+  // one, 1.0 on the rim. The core is a deeper red than the edge.
+  let offset = warped - GAS_SPOT_CENTER;
+  let horizontal = (offset.x * offset.x + offset.z * offset.z).sqrt() / GAS_SPOT_SIZE.x;
+  let vertical = offset.y / GAS_SPOT_SIZE.y;
+  let spot_distance = (horizontal * horizontal + vertical * vertical).sqrt();
+  let spot = ((1.0 - spot_distance) / GAS_SPOT_EDGE).clamp(0.0, 1.0);
+  let spot_color = Color::from_hsv(12.0, 0.45 + 0.2 * (1.0 - spot_distance).max(0.0), 0.85);
+  let final_color = band_color.lerp(&spot_color, spot);
+
+  apply_lighting(final_color, fragment, uniforms)
 }
 
 
 
 fn solar_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-  let zoom = 20.0;
-  let speed = 0.2; 
-  let time = uniforms.time as f32 * speed;
+  let zoom = 20.0 * uniforms.noise_params.zoom;
+  let speed = 0.2 * uniforms.noise_params.speed;
+  let time = uniforms.animation_time() * speed;
 
-  let x = fragment.vertex_position.x;
-  let y = fragment.vertex_position.y;
-  let z = fragment.depth;
+  let x = fragment.object_position.x;
+  let y = fragment.object_position.y;
+  let z = fragment.object_position.z;
 
   let noise_value1 = uniforms.noise.get_noise_3d(
       x * zoom + time,
@@ -289,9 +616,19 @@ fn solar_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
   ).abs();
   let combined_noise = (noise_value1 + noise_value2) * 0.5;
 
-  let core_color = Color::new(255, 140, 0);   
-  let flare_color = Color::new(255, 69, 0);   
-  let corona_color = Color::new(255, 215, 0);  
+  // Let the whole palette drift a few degrees of hue back and forth so the
+  // surface slowly shifts between orange and yellow.
+  let hue_shift = (time * 0.05).sin() * 8.0;
+  let shift_hue = |color: Color| {
+      let (h, s, v) = color.to_hsv();
+      Color::from_hsv(h + hue_shift, s, v)
+  };
+
+  let core_color = shift_hue(Color::new(255, 140, 0));
+  let flare_color = shift_hue(Color::new(255, 69, 0));
+  let corona_color = shift_hue(Color::new(255, 215, 0));
+
+  let combined_noise = dither(combined_noise, fragment, uniforms);
 
   let final_color = if combined_noise < 0.3 {
       corona_color  
@@ -301,18 +638,18 @@ fn solar_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
       flare_color   
   };
 
-  final_color * fragment.intensity
+  apply_lighting(final_color, fragment, uniforms)
 }
 
 
 fn earth_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
-  let zoom = 20.0; 
-  let speed = 0.1; 
-  let time = uniforms.time as f32 * speed;
+  let zoom = 20.0 * uniforms.noise_params.zoom;
+  let speed = 0.1 * uniforms.noise_params.speed;
+  let time = uniforms.animation_time() * speed;
 
-  let x = fragment.vertex_position.x;
-  let y = fragment.vertex_position.y;
-  let z = fragment.depth;
+  let x = fragment.object_position.x;
+  let y = fragment.object_position.y;
+  let z = fragment.object_position.z;
 
   let noise_value1 = uniforms.noise.get_noise_3d(
       x * zoom + time,
@@ -330,6 +667,8 @@ fn earth_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
   let land_color = Color::new(34, 139, 34); 
   let mountain_color = Color::new(139, 69, 19);   
 
+  let combined_noise = dither(combined_noise, fragment, uniforms);
+
   let final_color = if combined_noise < 0.3 {
       ocean_color   
   } else if combined_noise < 0.6 {
@@ -338,6 +677,230 @@ fn earth_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
       mountain_color 
   };
 
-  final_color * fragment.intensity
+  // Day/night terminator: a warm band where the light grazes the surface,
+  // and scattered city lights on land once it is fully dark
+  let sun_facing = fragment.normal.dot(&light_direction_at(&fragment.world_position, uniforms));
+  let twilight = (1.0 - (sun_facing / 0.15).abs()).clamp(0.0, 1.0);
+  let darkness = (-sun_facing / 0.2).clamp(0.0, 1.0);
+
+  let lit = apply_lighting(final_color, fragment, uniforms);
+  let lit = lit.lerp(&Color::new(255, 120, 60), twilight * 0.35);
+
+  let city_noise = uniforms.noise.get_noise_3d(x * 90.0, y * 90.0, z * 90.0);
+  let lit = if combined_noise >= 0.3 && city_noise > 0.55 {
+      lit + Color::new(255, 200, 90) * (darkness * 0.8)
+  } else {
+      lit
+  };
+
+  // Soft atmospheric glow along the silhouette
+  lit + Color::new(90, 200, 255) * (rim_intensity(fragment, uniforms, 3.0) * 0.7)
 }
 
+
+fn metal_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
+  let view_dir = (fragment.world_position - uniforms.camera_position).normalize();
+  let normal = fragment.normal.normalize();
+  let reflected = view_dir - normal * 2.0 * view_dir.dot(&normal);
+
+  let base_color = uniforms.material.base_color;
+  let reflection = uniforms.environment.sample(&reflected) * 0.9;
+
+  apply_lighting(base_color, fragment, uniforms) + reflection
+}
+
+// Pale ice plains from a broad noise octave, with a finer octave on top.
+// Where the fine noise crosses zero the ice is cracked; the high values form
+// bright ridges that also catch small specular glints.
+fn ice_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
+  let p = fragment.object_position;
+
+  let broad = uniforms.noise.get_noise_3d(p.x * 3.0, p.y * 3.0, p.z * 3.0);
+  let fine = uniforms.noise.get_noise_3d(p.x * 12.0 + 40.0, p.y * 12.0, p.z * 12.0);
+  let combined = (broad * 0.65 + fine * 0.35) * 0.5 + 0.5;
+
+  let plain_color = Color::new(190, 220, 235);
+  let ridge_color = Color::new(245, 250, 255);
+  let crack_color = Color::new(90, 130, 165);
+
+  let ridge = ((combined - 0.55) / 0.3).clamp(0.0, 1.0);
+  let crack = (1.0 - fine.abs() / 0.04).clamp(0.0, 1.0);
+  let surface = plain_color.lerp(&ridge_color, ridge).lerp(&crack_color, crack);
+
+  let view_dir = (uniforms.camera_position - fragment.world_position).normalize();
+  let light_dir = light_direction_at(&fragment.world_position, uniforms);
+  let (_, glint) = blinn_phong(fragment.normal, light_dir, view_dir, 96.0);
+
+  apply_lighting(surface, fragment, uniforms) + Color::new(255, 255, 255) * (glint * ridge * 0.6)
+}
+
+// Ocean world: two noise layers scrolling in opposite directions make the
+// swell, which shades between deep and shallow water. Where both layers
+// peak together the crests catch the light as white sparkles, only on the
+// lit side.
+fn water_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
+  let p = fragment.object_position;
+  let t = uniforms.animation_time() * 0.4;
+
+  let swell = uniforms.noise.get_noise_3d(p.x * 250.0 + t, p.y * 250.0, p.z * 250.0 - t);
+  let chop = uniforms.noise.get_noise_3d(p.x * 600.0 - t * 1.7, p.y * 600.0 + t, p.z * 600.0);
+  let waves = (swell * 0.6 + chop * 0.4) * 0.5 + 0.5;
+
+  let deep_color = Color::new(8, 40, 110);
+  let shallow_color = Color::new(30, 120, 190);
+  let water = deep_color.lerp(&shallow_color, waves);
+
+  let sparkle = ((waves - 0.72) / 0.1).clamp(0.0, 1.0) * fragment.intensity;
+
+  apply_lighting(water, fragment, uniforms) + Color::new(255, 255, 255).scale(sparkle)
+}
+
+// Image texture wrapped around the body. The bundled sphere.obj maps the
+// whole image onto every face, so its UVs are replaced by an equirectangular
+// mapping of the object-space direction. Without a texture the material's
+// base color is used, so a failed load still draws the body.
+fn textured_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
+  let color = match uniforms.texture {
+    Some(texture) => {
+      let direction = fragment.object_position.try_normalize(f32::EPSILON).unwrap_or(Vec3::y());
+      let u = 0.5 + direction.z.atan2(direction.x) / (2.0 * PI);
+      let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+      texture.sample(u, v)
+    }
+    None => uniforms.material.base_color,
+  };
+
+  apply_lighting(color, fragment, uniforms)
+}
+
+// Shows the color interpolated from the mesh's vertices (from the MTL diffuse
+// color when the OBJ has one), shaded by the light intensity alone. Handy for
+// checking what a model carries before a procedural shader replaces it.
+fn vertex_color_shader(fragment: &Fragment, _uniforms: &Uniforms) -> Color {
+  fragment.color * fragment.intensity
+}
+
+// Radial position across the ring and the band density there, both in [0, 1]
+fn ring_density(fragment: &Fragment, uniforms: &Uniforms) -> (f32, f32) {
+  let radial = fragment.tex_coords.x.clamp(0.0, 1.0);
+  let band_noise = uniforms.noise.get_noise_2d(radial * 60.0, 0.0);
+  (radial, (band_noise * 0.5 + 0.5).clamp(0.0, 1.0))
+}
+
+// Dense bands are more opaque, and both edges fade out
+fn ring_alpha(fragment: &Fragment, uniforms: &Uniforms) -> f32 {
+  let (radial, density) = ring_density(fragment, uniforms);
+  let edge_fade = (radial / 0.15).min((1.0 - radial) / 0.15).clamp(0.0, 1.0);
+  edge_fade * (0.3 + 0.7 * density)
+}
+
+// How much of the shell's air a view ray crosses, in planet radii, turned
+// into opacity with exponential (Beer-Lambert) falloff. On a sphere, the ray
+// through a fragment whose normal makes cosine `c` with the view passes at
+// sqrt(1 - c^2) shell radii from the center. Inside the planet's silhouette
+// only the air in front of the surface counts. Outside it the ray crosses
+// the whole chord. So the shell is thickest just past the planet's limb and
+// fades to nothing at its own edge.
+fn atmosphere_alpha(fragment: &Fragment, uniforms: &Uniforms) -> f32 {
+  let view_dir = (uniforms.camera_position - fragment.world_position).normalize();
+  let c = fragment.normal.dot(&view_dir).clamp(0.0, 1.0);
+  let planet = 1.0 / ATMOSPHERE_SCALE;
+  let impact_squared = 1.0 - c * c;
+
+  let path = if impact_squared < planet * planet {
+      c - (planet * planet - impact_squared).sqrt()
+  } else {
+      2.0 * c
+  };
+  let optical_depth = ATMOSPHERE_DENSITY * path * ATMOSPHERE_SCALE;
+  (1.0 - (-optical_depth).exp()) * atmosphere_daylight(fragment, uniforms)
+}
+
+// Scattered sunlight reaches a little past the terminator, then the air goes
+// dark and clear so the night side (and its city lights) show through
+fn atmosphere_daylight(fragment: &Fragment, uniforms: &Uniforms) -> f32 {
+  let sun_facing = fragment.normal.dot(&light_direction_at(&fragment.world_position, uniforms));
+  ((sun_facing + 0.3) / 1.3).clamp(0.0, 1.0)
+}
+
+// Translucent blue shell drawn around earth-like planets; its opacity comes
+// from `atmosphere_alpha`
+fn atmosphere_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
+  ATMOSPHERE_COLOR * atmosphere_daylight(fragment, uniforms).sqrt()
+}
+
+// Banded ring using the radial UV; its opacity comes from `ring_alpha`
+fn ring_shader(fragment: &Fragment, uniforms: &Uniforms) -> Color {
+  let (_, density) = ring_density(fragment, uniforms);
+
+  let dust_color = Color::new(200, 180, 140);
+  let ice_color = Color::new(235, 225, 205);
+  let ring_color = dust_color.lerp(&ice_color, density);
+
+  apply_lighting(ring_color, fragment, uniforms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dark_side_is_tinted_by_ambient_color() {
+        let uniforms = Uniforms {
+            ambient: 0.1,
+            ambient_color: Color::new(70, 80, 110),
+            ..Uniforms::for_test(64.0, 48.0)
+        };
+        let base = Color::new(200, 100, 50);
+
+        // 10% of the base plus base * ambient color / 255
+        assert_eq!(ambient_diffuse(base, 0.0, &uniforms), Color::new(74, 41, 26));
+        assert_eq!(ambient_diffuse(base, 1.0, &uniforms), base);
+    }
+
+    #[test]
+    fn dithering_mixes_pixels_at_a_band_boundary() {
+        // Just below the earth shader's ocean/land threshold, over one Bayer tile
+        let value = 0.29;
+        let above_threshold = |strength: f32| {
+            let uniforms = Uniforms { dither_strength: strength, ..Uniforms::for_test(64.0, 48.0) };
+            (0..16)
+                .filter(|i| {
+                    let fragment = Fragment::new((i % 4) as f32, (i / 4) as f32, Color::black(), 0.0, Vec3::z(), Vec3::zeros());
+                    dither(value, &fragment, &uniforms) >= 0.3
+                })
+                .count()
+        };
+
+        assert_eq!(above_threshold(0.0), 0);
+        let mixed = above_threshold(crate::DITHER_STRENGTH);
+        assert!(mixed > 0 && mixed < 16, "{} of 16 pixels above the threshold", mixed);
+    }
+
+    #[test]
+    fn lava_heat_stays_in_unit_range() {
+        let samples = (-12..=12).map(|i| i as f32 / 10.0);
+        for a in samples.clone() {
+            for b in samples.clone() {
+                let heat = lava_heat(a, b);
+                assert!((0.0..=1.0).contains(&heat), "lava_heat({}, {}) = {}", a, b, heat);
+            }
+        }
+        assert_eq!(lava_heat(-1.0, -1.0), 0.0);
+        assert_eq!(lava_heat(1.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn spin_rotates_normals_with_the_surface() {
+        // A quarter turn about Y, scaled and moved, as a spinning body would be
+        let uniforms = Uniforms {
+            model_matrix: crate::create_model_matrix(Vec3::new(3.0, 1.0, 0.0), 2.0, Vec3::new(0.0, PI / 2.0, 0.0)),
+            ..Uniforms::for_test(64.0, 48.0)
+        };
+        let vertex = Vertex::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec2::zeros());
+        let shaded = vertex_shader(&vertex, &uniforms);
+
+        assert!((shaded.world_position - Vec3::new(3.0, 1.0, -2.0)).magnitude() < 1e-5);
+        assert!((shaded.transformed_normal.normalize() - Vec3::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+    }
+}
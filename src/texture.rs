@@ -0,0 +1,118 @@
+use std::fs;
+use std::io;
+use crate::color::Color;
+
+// RGB image sampled with wrap-around addressing in both directions
+pub struct Texture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Texture {
+    // Reads a binary PPM (P6, 8 bits per channel). Header comments are allowed.
+    pub fn load_ppm(path: &str) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, message));
+
+        let mut fields = Vec::with_capacity(4);
+        let mut cursor = 0;
+        while fields.len() < 4 {
+            while cursor < data.len() && data[cursor].is_ascii_whitespace() {
+                cursor += 1;
+            }
+            if cursor < data.len() && data[cursor] == b'#' {
+                while cursor < data.len() && data[cursor] != b'\n' {
+                    cursor += 1;
+                }
+                continue;
+            }
+
+            let start = cursor;
+            while cursor < data.len() && !data[cursor].is_ascii_whitespace() {
+                cursor += 1;
+            }
+            if start == cursor {
+                return Err(invalid("truncated header"));
+            }
+            fields.push(String::from_utf8_lossy(&data[start..cursor]).into_owned());
+        }
+        // Exactly one whitespace byte separates the header from the pixels
+        cursor += 1;
+
+        if fields[0] != "P6" {
+            return Err(invalid("only binary PPM (P6) is supported"));
+        }
+        let parse = |field: &str| field.parse::<usize>().map_err(|_| invalid("bad header value"));
+        let (width, height, max_value) = (parse(&fields[1])?, parse(&fields[2])?, parse(&fields[3])?);
+        if width == 0 || height == 0 || max_value != 255 {
+            return Err(invalid("expected a non-empty image with 8-bit channels"));
+        }
+
+        // A header claiming a huge image must not overflow the end offset
+        let end = width.checked_mul(height)
+            .and_then(|texels| texels.checked_mul(3))
+            .and_then(|bytes| bytes.checked_add(cursor))
+            .ok_or_else(|| invalid("truncated pixel data"))?;
+        let body = data.get(cursor..end).ok_or_else(|| invalid("truncated pixel data"))?;
+        let pixels = body.chunks(3).map(|rgb| Color::new(rgb[0], rgb[1], rgb[2])).collect();
+
+        Ok(Texture { width, height, pixels })
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.rem_euclid(self.height as i64) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    // Bilinear filtering between the four texels around (u, v). Texel centers
+    // sit at half-integer coordinates, and both axes wrap.
+    pub fn sample(&self, u: f32, v: f32) -> Color {
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0).lerp(&self.texel(x0 + 1, y0), tx);
+        let bottom = self.texel(x0, y0 + 1).lerp(&self.texel(x0 + 1, y0 + 1), tx);
+        top.lerp(&bottom, ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_blends_four_texels() {
+        let texture = Texture {
+            width: 2,
+            height: 2,
+            pixels: vec![
+                Color::new(255, 0, 0), Color::new(0, 255, 0),
+                Color::new(0, 0, 255), Color::new(255, 255, 255),
+            ],
+        };
+
+        // A quarter of the way across and three quarters of the way down
+        // between the four texel centers
+        let (u, v) = ((0.25 + 0.5) / 2.0, (0.75 + 0.5) / 2.0);
+        let top = Color::new(255, 0, 0).lerp(&Color::new(0, 255, 0), 0.25);
+        let bottom = Color::new(0, 0, 255).lerp(&Color::new(255, 255, 255), 0.25);
+        assert_eq!(texture.sample(u, v), top.lerp(&bottom, 0.75));
+        assert_eq!(texture.sample(u, v), Color::new(96, 64, 191));
+    }
+
+    #[test]
+    fn oversized_header_is_truncated_data() {
+        let path = std::env::temp_dir().join("sr_02_line_oversized.ppm");
+        fs::write(&path, format!("P6\n{} 2 255\n\0\0\0", usize::MAX)).unwrap();
+        let error = Texture::load_ppm(path.to_str().unwrap()).err().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().ends_with("truncated pixel data"), "{}", error);
+    }
+}
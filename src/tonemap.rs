@@ -0,0 +1,47 @@
+// Operators that compress linear color, which may run past 1.0 for bright
+// emissive surfaces, back into the displayable [0, 1] range. Applied per
+// channel when the framebuffer is resolved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapping {
+    // Plain clamp, so anything brighter than white clips
+    None,
+    // c / (c + 1): never clips, but also darkens midtones
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve: keeps midtones close to the
+    // input and rolls highlights off towards white
+    Aces,
+}
+
+impl ToneMapping {
+    pub fn map(self, value: f32) -> f32 {
+        let value = value.max(0.0);
+        let mapped = match self {
+            ToneMapping::None => value,
+            ToneMapping::Reinhard => value / (value + 1.0),
+            ToneMapping::Aces => (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14),
+        };
+        mapped.clamp(0.0, 1.0)
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapping::None => ToneMapping::Reinhard,
+            ToneMapping::Reinhard => ToneMapping::Aces,
+            ToneMapping::Aces => ToneMapping::None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ToneMapping::None => "none",
+            ToneMapping::Reinhard => "reinhard",
+            ToneMapping::Aces => "aces",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [ToneMapping::None, ToneMapping::Reinhard, ToneMapping::Aces]
+            .into_iter()
+            .find(|operator| operator.name() == name)
+    }
+}
@@ -1,56 +1,109 @@
-use nalgebra_glm::{Vec3, dot};
+use nalgebra_glm::Vec3;
 use crate::fragment::Fragment;
 use crate::vertex::Vertex;
 use crate::color::Color;
 
-pub fn triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex) -> Vec<Fragment> {
-  let mut fragments = Vec::new();
+// Screen positions are snapped to 1/2^SUBPIXEL_BITS of a pixel before the
+// coverage test, which then runs in exact integer arithmetic. Vertices
+// still move smoothly (in 1/256 px steps) instead of jumping whole pixels.
+const SUBPIXEL_BITS: u32 = 8;
+const SUBPIXEL_SCALE: f32 = (1 << SUBPIXEL_BITS) as f32;
+const HALF_PIXEL: i64 = 1 << (SUBPIXEL_BITS - 1);
+
+// Appends the triangle's fragments to `fragments`, so one buffer can be
+// reused for a whole mesh instead of allocating per triangle. Lighting is
+// left to the fragment shader.
+pub fn triangle(v1: &Vertex, v2: &Vertex, v3: &Vertex, fragments: &mut Vec<Fragment>) {
   let (a, b, c) = (v1.transformed_position, v2.transformed_position, v3.transformed_position);
 
+  // Clipping keeps vertices on screen, so anything else is a NaN or infinity
+  // that would saturate the fixed-point conversion
+  if ![a, b, c].iter().all(|v| v.x.is_finite() && v.y.is_finite()) {
+    return;
+  }
+
   let (min_x, min_y, max_x, max_y) = calculate_bounding_box(&a, &b, &c);
 
-  let light_dir = Vec3::new(0.0, 0.0, 1.0);
+  let (fa, fb, fc) = (to_fixed(&a), to_fixed(&b), to_fixed(&c));
+  let triangle_area = edge_function_fixed(fa, fb, fc);
 
-  let triangle_area = edge_function(&a, &b, &c);
+  // Zero-area triangles would divide by zero in the barycentric step
+  if triangle_area == 0 {
+    return;
+  }
+
+  // Which of the three edges (opposite a, b and c) own the pixels lying
+  // exactly on them
+  let orientation = triangle_area.signum();
+  let owns_edge = [
+    is_top_left(fb, fc, orientation),
+    is_top_left(fc, fa, orientation),
+    is_top_left(fa, fb, orientation),
+  ];
 
   for y in min_y..=max_y {
     for x in min_x..=max_x {
-      let point = Vec3::new(x as f32 + 0.5, y as f32 + 0.5, 0.0);
+      let point = ((x as i64) << SUBPIXEL_BITS | HALF_PIXEL, (y as i64) << SUBPIXEL_BITS | HALF_PIXEL);
 
-      let (w1, w2, w3) = barycentric_coordinates(&point, &a, &b, &c, triangle_area);
+      let edges = [
+        edge_function_fixed(fb, fc, point),
+        edge_function_fixed(fc, fa, point),
+        edge_function_fixed(fa, fb, point),
+      ];
+      let covered = edges.iter().zip(owns_edge).all(|(&edge, owned)| {
+        let edge = edge * orientation;
+        edge > 0 || (edge == 0 && owned)
+      });
 
-      if w1 >= 0.0 && w1 <= 1.0 && 
-         w2 >= 0.0 && w2 <= 1.0 &&
-         w3 >= 0.0 && w3 <= 1.0 {
+      if covered {
+        let area = triangle_area as f32;
+        let (w1, w2, w3) = (edges[0] as f32 / area, edges[1] as f32 / area, edges[2] as f32 / area);
 
-        let normal = v1.transformed_normal * w1 + v2.transformed_normal * w2 + v3.transformed_normal * w3;
-        let normal = normal.normalize();
+        // Screen-space weights are right for depth (already divided by w) but
+        // not for anything else: those are weighted by 1/w and renormalized
+        let (p1, p2, p3) = perspective_weights(w1, w2, w3, v1, v2, v3);
 
-        let intensity = dot(&normal, &light_dir).max(0.0);
+        let normal = v1.transformed_normal * p1 + v2.transformed_normal * p2 + v3.transformed_normal * p3;
+        let normal = normal.try_normalize(f32::EPSILON).unwrap_or(normal);
 
-        let base_color = Color::new(100, 100, 100);
-        let lit_color = base_color * intensity;
+        let world_position = v1.world_position * p1 + v2.world_position * p2 + v3.world_position * p3;
 
         let depth = a.z * w1 + b.z * w2 + c.z * w3;
 
-        let vertex_position = v1.position * w1 + v2.position * w2 + v3.position * w3;
-
-        fragments.push(
-            Fragment::new(
-                x as f32,
-                y as f32,
-                lit_color,
-                depth,
-                normal,
-                intensity,
-                vertex_position,
-            )
+        let object_position = v1.position * p1 + v2.position * p2 + v3.position * p3;
+
+        let tex_coords = v1.tex_coords * p1 + v2.tex_coords * p2 + v3.tex_coords * p3;
+
+        let color = v1.color.to_rgb() * p1 + v2.color.to_rgb() * p2 + v3.color.to_rgb() * p3;
+
+        let mut fragment = Fragment::new(
+            x as f32,
+            y as f32,
+            Color::from_rgb(&color),
+            depth,
+            normal,
+            object_position,
         );
+        fragment.tex_coords = tex_coords;
+        fragment.world_position = world_position;
+
+        fragments.push(fragment);
       }
     }
   }
+}
+
+// Counter-clockwise faces from the OBJ come out of the y-flipping viewport
+// transform with a positive signed area
+pub fn is_front_facing(a: &Vec3, b: &Vec3, c: &Vec3) -> bool {
+    edge_function(a, b, c) > 0.0
+}
 
-  fragments
+// Unit normal of the plane through three points, on the side the vertices
+// wind counter-clockwise around (outwards for the OBJ's front faces). None
+// for degenerate triangles.
+pub fn face_normal(a: &Vec3, b: &Vec3, c: &Vec3) -> Option<Vec3> {
+    (b - a).cross(&(c - a)).try_normalize(f32::EPSILON)
 }
 
 fn calculate_bounding_box(v1: &Vec3, v2: &Vec3, v3: &Vec3) -> (i32, i32, i32, i32) {
@@ -62,16 +115,205 @@ fn calculate_bounding_box(v1: &Vec3, v2: &Vec3, v3: &Vec3) -> (i32, i32, i32, i3
     (min_x, min_y, max_x, max_y)
 }
 
-fn barycentric_coordinates(p: &Vec3, a: &Vec3, b: &Vec3, c: &Vec3, area: f32) -> (f32, f32, f32) {
-    let w1 = edge_function(b, c, p) / area;
-    let w2 = edge_function(c, a, p) / area;
-    let w3 = edge_function(a, b, p) / area;
+// Screen x and y in fixed point, SUBPIXEL_BITS fractional bits
+fn to_fixed(v: &Vec3) -> (i64, i64) {
+    ((v.x * SUBPIXEL_SCALE).round() as i64, (v.y * SUBPIXEL_SCALE).round() as i64)
+}
+
+// Turns screen-space barycentrics into perspective-correct ones using each
+// vertex's clip-space w. With an orthographic projection every w is 1 and the
+// weights come back unchanged.
+fn perspective_weights(w1: f32, w2: f32, w3: f32, v1: &Vertex, v2: &Vertex, v3: &Vertex) -> (f32, f32, f32) {
+    let (q1, q2, q3) = (
+        w1 / v1.clip_position.w,
+        w2 / v2.clip_position.w,
+        w3 / v3.clip_position.w,
+    );
+    let sum = q1 + q2 + q3;
+    if !sum.is_finite() || sum.abs() <= f32::EPSILON {
+        return (w1, w2, w3);
+    }
+
+    (q1 / sum, q2 / sum, q3 / sum)
+}
 
-    (w1, w2, w3)
+// Top-left fill rule: a pixel center lying exactly on an edge belongs to
+// the triangle only if that edge is a left edge, or a horizontal top edge.
+// Two triangles sharing an edge see it with opposite orientations, and the
+// integer edge functions give them exactly opposite values, so exactly one
+// of them takes those pixels: no cracks and no double writes.
+// `orientation` is the sign of the triangle's area; in screen space (y down)
+// a positive-area triangle's left edges run downwards and its top edge runs
+// right to left.
+fn is_top_left(start: (i64, i64), end: (i64, i64), orientation: i64) -> bool {
+    let (dx, dy) = ((end.0 - start.0) * orientation, (end.1 - start.1) * orientation);
+    dy > 0 || (dy == 0 && dx < 0)
+}
+
+// edge_function on fixed-point coordinates. Exact: with on-screen
+// coordinates the products stay far below i64::MAX.
+fn edge_function_fixed(a: (i64, i64), b: (i64, i64), c: (i64, i64)) -> i64 {
+    (c.0 - a.0) * (b.1 - a.1) - (c.1 - a.1) * (b.0 - a.0)
 }
 
 fn edge_function(a: &Vec3, b: &Vec3, c: &Vec3) -> f32 {
     (c.x - a.x) * (b.y - a.y) - (c.y - a.y) * (b.x - a.x)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::Vec2;
+    use std::collections::HashSet;
+    use crate::framebuffer::Framebuffer;
 
+    // Already in screen space, with w = 1
+    fn screen_vertex(x: f32, y: f32, z: f32) -> Vertex {
+        Vertex::new(Vec3::new(x, y, z), Vec3::new(0.0, 0.0, 1.0), Vec2::zeros())
+    }
+
+    fn rasterize(corners: &[Vertex; 3]) -> Vec<Fragment> {
+        let mut fragments = Vec::new();
+        triangle(&corners[0], &corners[1], &corners[2], &mut fragments);
+        fragments
+    }
+
+    fn covered_pixels(corners: &[Vertex; 3]) -> HashSet<(usize, usize)> {
+        rasterize(corners).iter()
+            .map(|fragment| (fragment.position.x as usize, fragment.position.y as usize))
+            .collect()
+    }
+
+    #[test]
+    fn nearer_triangle_wins_shared_pixels() {
+        let near = [screen_vertex(2.0, 2.0, 0.2), screen_vertex(30.0, 4.0, 0.2), screen_vertex(8.0, 28.0, 0.2)];
+        let far = [screen_vertex(4.0, 6.0, 0.6), screen_vertex(28.0, 10.0, 0.6), screen_vertex(20.0, 30.0, 0.6)];
+        let shared: Vec<(usize, usize)> = covered_pixels(&near).intersection(&covered_pixels(&far)).copied().collect();
+        assert!(shared.len() > 50);
+
+        for order in [[(&near, 0x0000FF), (&far, 0xFF0000)], [(&far, 0xFF0000), (&near, 0x0000FF)]] {
+            let mut framebuffer = Framebuffer::new(32, 32);
+            framebuffer.clear();
+            for (corners, color) in order {
+                framebuffer.set_current_color(color);
+                for fragment in rasterize(corners) {
+                    framebuffer.point(fragment.position.x as usize, fragment.position.y as usize, fragment.depth);
+                }
+            }
+
+            let blue = Color::from_hex(0x0000FF).to_rgb();
+            for &(x, y) in &shared {
+                assert!((framebuffer.get_depth(x, y) - 0.2).abs() < 1e-6);
+                assert_eq!(framebuffer.hdr[y * 32 + x], blue);
+            }
+        }
+    }
+
+    #[test]
+    fn shared_edge_has_no_gaps_or_double_writes() {
+        // A quad at fractional coordinates, split along its diagonal
+        let corners = [
+            screen_vertex(0.3, 0.7, 0.5),
+            screen_vertex(15.6, 1.2, 0.5),
+            screen_vertex(14.8, 13.9, 0.5),
+            screen_vertex(1.1, 15.4, 0.5),
+        ];
+        let halves = [
+            [corners[0].clone(), corners[1].clone(), corners[2].clone()],
+            [corners[0].clone(), corners[2].clone(), corners[3].clone()],
+        ];
+
+        let mut writes = [[0u32; 16]; 16];
+        for half in &halves {
+            for fragment in rasterize(half) {
+                writes[fragment.position.y as usize][fragment.position.x as usize] += 1;
+            }
+        }
+
+        // Every pixel center inside the quad is written exactly once, seam
+        // included; the rest are never written
+        for (y, row) in writes.iter().enumerate() {
+            for (x, &count) in row.iter().enumerate() {
+                let center = Vec3::new(x as f32 + 0.5, y as f32 + 0.5, 0.0);
+                let sides: Vec<f32> = (0..4)
+                    .map(|i| edge_function(&corners[i].position, &corners[(i + 1) % 4].position, &center))
+                    .collect();
+                let inside = sides.iter().all(|&side| side > 0.0) || sides.iter().all(|&side| side < 0.0);
+                assert_eq!(count, u32::from(inside), "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn sub_pixel_rotation_changes_coverage_gradually() {
+        // Each step moves the corners by about 0.01 px, a whole pixel in total
+        let center = (32.4, 31.7);
+        let rotated = |angle: f32| -> [Vertex; 3] {
+            [0.3f32, 2.4, 4.4].map(|corner| {
+                let (sin, cos) = (corner + angle).sin_cos();
+                screen_vertex(center.0 + 24.0 * cos, center.1 + 24.0 * sin, 0.5)
+            })
+        };
+
+        let mut previous = covered_pixels(&rotated(0.0));
+        let mut steps_with_changes = 0;
+        for step in 1..=100 {
+            let current = covered_pixels(&rotated(step as f32 * 0.0004));
+            let changed = previous.symmetric_difference(&current).count();
+            assert!(changed <= 4, "step {} changed {} pixels", step, changed);
+            steps_with_changes += usize::from(changed > 0);
+            previous = current;
+        }
+        assert!(steps_with_changes >= 20, "coverage changed in only {} steps", steps_with_changes);
+    }
+
+    #[test]
+    fn foreshortened_quad_midpoint_uv_is_perspective_correct() {
+        // A rectangular floor receding from w = 1 at the bottom edge to w = 10
+        // at the top, so the far edge is a tenth as wide on screen, with v
+        // running from 0 near to 1 far
+        let corner = |x: f32, y: f32, w: f32, uv: Vec2| {
+            let mut vertex = screen_vertex(x, y, 0.5);
+            vertex.clip_position.w = w;
+            vertex.tex_coords = uv;
+            vertex
+        };
+        let near_left = corner(4.5, 60.5, 1.0, Vec2::new(0.0, 0.0));
+        let near_right = corner(60.5, 60.5, 1.0, Vec2::new(1.0, 0.0));
+        let far_right = corner(35.3, 4.5, 10.0, Vec2::new(1.0, 1.0));
+        let far_left = corner(29.7, 4.5, 10.0, Vec2::new(0.0, 1.0));
+
+        let mut fragments = rasterize(&[near_left.clone(), near_right, far_right.clone()]);
+        fragments.extend(rasterize(&[near_left, far_right, far_left]));
+        let midpoint = fragments.iter()
+            .find(|fragment| fragment.position == Vec2::new(32.0, 32.0))
+            .unwrap();
+
+        // Halfway up the screen is a tenth as far into the quad as it looks:
+        // (0.5 / 10) / (0.5 / 1 + 0.5 / 10) instead of the linear 0.5
+        let linear = 0.5;
+        let corrected = 1.0 / 11.0;
+        assert!((midpoint.tex_coords.y - corrected).abs() < 1e-4, "{}", midpoint.tex_coords.y);
+        assert!((midpoint.tex_coords.y - linear).abs() > 0.4);
+        assert!((midpoint.tex_coords.x - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn textured_cube_uv_at_centroid() {
+        let vertices = crate::obj::Obj::load("assets/models/textured_cube.obj").unwrap().get_vertex_array();
+        // The front face's first triangle, scaled onto a 64 x 64 screen with
+        // y flipped; its centroid lands on the center of pixel (42, 42)
+        let corners: [Vertex; 3] = std::array::from_fn(|i| {
+            let mut vertex = vertices[i].clone();
+            vertex.transformed_position = Vec3::new(vertex.position.x * 60.0 + 32.5, 32.5 - vertex.position.y * 60.0, 0.5);
+            vertex
+        });
+        let centroid = rasterize(&corners).into_iter()
+            .find(|fragment| fragment.position == Vec2::new(42.0, 42.0))
+            .unwrap();
+
+        let expected = (corners[0].tex_coords + corners[1].tex_coords + corners[2].tex_coords) / 3.0;
+        assert_eq!(expected, Vec2::new(2.0 / 3.0, 1.0 / 3.0));
+        assert!((centroid.tex_coords - expected).magnitude() < 1e-5, "{:?}", centroid.tex_coords);
+    }
+}
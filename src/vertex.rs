@@ -1,14 +1,19 @@
-use nalgebra_glm::{Vec2, Vec3};
+use nalgebra_glm::{Vec2, Vec3, Vec4};
 use crate::color::Color;
 
 #[derive(Clone, Debug)]
 pub struct Vertex {
   pub position: Vec3,
   pub normal: Vec3,
+  // UVs exactly as the OBJ's `vt` gives them, (0, 0) when it has none. OBJ
+  // puts v = 0 at the bottom of the image while Texture::sample puts it at
+  // the top, so sampling with these takes 1 - v.
   pub tex_coords: Vec2,
   pub color: Color,
+  pub clip_position: Vec4,
   pub transformed_position: Vec3,
   pub transformed_normal: Vec3,
+  pub world_position: Vec3,
 }
 
 impl Vertex {
@@ -18,8 +23,10 @@ impl Vertex {
       normal,
       tex_coords,
       color: Color::black(),
+      clip_position: Vec4::new(position.x, position.y, position.z, 1.0),
       transformed_position: position,
       transformed_normal: normal,
+      world_position: position,
     }
   }
 
@@ -29,8 +36,10 @@ impl Vertex {
       normal: Vec3::new(0.0, 0.0, 0.0),
       tex_coords: Vec2::new(0.0, 0.0),
       color,
+      clip_position: Vec4::new(position.x, position.y, position.z, 1.0),
       transformed_position: Vec3::new(0.0, 0.0, 0.0),
       transformed_normal: Vec3::new(0.0, 0.0, 0.0),
+      world_position: position,
     }
   }
 
@@ -47,8 +56,10 @@ impl Default for Vertex {
       normal: Vec3::new(0.0, 1.0, 0.0),
       tex_coords: Vec2::new(0.0, 0.0),
       color: Color::black(),
+      clip_position: Vec4::new(0.0, 0.0, 0.0, 1.0),
       transformed_position: Vec3::new(0.0, 0.0, 0.0),
       transformed_normal: Vec3::new(0.0, 1.0, 0.0),
+      world_position: Vec3::new(0.0, 0.0, 0.0),
     }
   }
 }